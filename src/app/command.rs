@@ -1,5 +1,6 @@
 // Defines commands which are used to order behavior to system via implemented shell.
 
+use crate::debug::reverse;
 use crate::emulator::Emulator;
 
 pub trait Command {
@@ -57,3 +58,256 @@ impl Command for AddBreakpointCommand {
         emulator.add_breakpoint(self.address)
     }
 }
+
+// BREAK ON LD B,B
+pub struct ToggleTrapCommand;
+impl Command for ToggleTrapCommand {
+    fn execute(&self, emulator: &mut Emulator) -> String {
+        if emulator.toggle_break_on_trap() {
+            "Breaking on LD B,B.".to_string()
+        } else {
+            "Ignoring LD B,B.".to_string()
+        }
+    }
+}
+
+// BACKTRACE
+pub struct BacktraceCommand;
+impl Command for BacktraceCommand {
+    fn execute(&self, emulator: &mut Emulator) -> String {
+        emulator.backtrace().join("\n")
+    }
+}
+
+// LOAD SYMBOLS
+pub struct LoadSymbolsCommand {
+    pub path: String,
+}
+impl Command for LoadSymbolsCommand {
+    fn execute(&self, emulator: &mut Emulator) -> String {
+        match emulator.load_symbols(&self.path) {
+            Ok(count) => format!("Loaded {} symbols from {}", count, self.path),
+            Err(e) => format!("Failed to load {}: {}", self.path, e),
+        }
+    }
+}
+
+// TOGGLE M-CYCLE ACCURATE BUS
+pub struct ToggleMCycleCommand;
+impl Command for ToggleMCycleCommand {
+    fn execute(&self, emulator: &mut Emulator) -> String {
+        if emulator.toggle_m_cycle_accurate() {
+            "M-cycle accurate bus enabled.".to_string()
+        } else {
+            "M-cycle accurate bus disabled.".to_string()
+        }
+    }
+}
+
+// TOGGLE DECODED INSTRUCTION CACHE
+pub struct ToggleCodeCacheCommand;
+impl Command for ToggleCodeCacheCommand {
+    fn execute(&self, emulator: &mut Emulator) -> String {
+        if emulator.toggle_code_cache() {
+            "Instruction cache enabled.".to_string()
+        } else {
+            "Instruction cache disabled.".to_string()
+        }
+    }
+}
+
+// TRACE (Gameboy Doctor log)
+pub struct StartTraceCommand {
+    pub path: String,
+}
+impl Command for StartTraceCommand {
+    fn execute(&self, emulator: &mut Emulator) -> String {
+        match emulator.start_trace(&self.path) {
+            Ok(_) => format!("Restarted the ROM, tracing to {}", self.path),
+            Err(e) => format!("Failed to trace to {}: {}", self.path, e),
+        }
+    }
+}
+
+pub struct StopTraceCommand;
+impl Command for StopTraceCommand {
+    fn execute(&self, emulator: &mut Emulator) -> String {
+        match emulator.stop_trace() {
+            Ok(true) => "Trace stopped.".to_string(),
+            Ok(false) => "No trace running.".to_string(),
+            Err(e) => format!("Trace stopped, the file is incomplete: {}", e),
+        }
+    }
+}
+
+// PROFILE
+pub struct ToggleProfilerCommand;
+impl Command for ToggleProfilerCommand {
+    fn execute(&self, emulator: &mut Emulator) -> String {
+        match emulator.stop_profiler() {
+            Some(report) => report,
+            None => {
+                emulator.start_profiler();
+                "Profiler started.".to_string()
+            }
+        }
+    }
+}
+
+pub struct SaveProfileCommand {
+    pub path: String,
+}
+impl Command for SaveProfileCommand {
+    fn execute(&self, emulator: &mut Emulator) -> String {
+        let Some(report) = emulator.stop_profiler() else {
+            return "Profiler is not running.".to_string();
+        };
+        match std::fs::write(&self.path, report) {
+            Ok(_) => format!("Profile written to {}", self.path),
+            Err(e) => format!("Failed to write {}: {}", self.path, e),
+        }
+    }
+}
+
+// MOVIE RECORDING / PLAYBACK
+pub struct RecordMovieCommand {
+    pub path: String,
+}
+impl Command for RecordMovieCommand {
+    fn execute(&self, emulator: &mut Emulator) -> String {
+        match emulator.start_recording(&self.path) {
+            Ok(_) => format!("Recording to {} from power on", self.path),
+            Err(e) => format!("Failed to start recording: {}", e),
+        }
+    }
+}
+
+pub struct PlayMovieCommand {
+    pub path: String,
+}
+impl Command for PlayMovieCommand {
+    fn execute(&self, emulator: &mut Emulator) -> String {
+        match emulator.play_movie(&self.path) {
+            Ok(version) if version != env!("CARGO_PKG_VERSION") => format!(
+                "Playing {}, recorded with version {}, playback may diverge",
+                self.path, version
+            ),
+            Ok(_) => format!("Playing {}", self.path),
+            Err(e) => format!("Failed to play {}: {}", self.path, e),
+        }
+    }
+}
+
+pub struct StopMovieCommand;
+impl Command for StopMovieCommand {
+    fn execute(&self, emulator: &mut Emulator) -> String {
+        match emulator.stop_movie() {
+            Ok(Some(path)) => format!("Movie saved to {}", path),
+            Ok(None) => "Movie stopped.".to_string(),
+            Err(e) => format!("Failed to save movie: {}", e),
+        }
+    }
+}
+
+// SAVE STATES
+pub struct SaveStateCommand {
+    pub target: String, // slot number or path
+}
+impl Command for SaveStateCommand {
+    fn execute(&self, emulator: &mut Emulator) -> String {
+        let path = emulator.state_path(&self.target);
+        match emulator.save_state(&path) {
+            Ok(_) => format!("State saved to {}", path),
+            Err(e) => format!("Failed to save state to {}: {}", path, e),
+        }
+    }
+}
+
+pub struct LoadStateCommand {
+    pub target: String,
+}
+impl Command for LoadStateCommand {
+    fn execute(&self, emulator: &mut Emulator) -> String {
+        let path = emulator.state_path(&self.target);
+        match emulator.load_state(&path) {
+            Ok(_) => format!("State loaded from {}", path),
+            Err(e) => format!("Failed to load state from {}: {}", path, e),
+        }
+    }
+}
+
+// REWIND
+pub struct RewindCommand {
+    pub steps: usize,
+}
+impl Command for RewindCommand {
+    fn execute(&self, emulator: &mut Emulator) -> String {
+        match emulator.rewind(self.steps) {
+            Ok(0) => "Nothing left to rewind".to_string(),
+            Ok(steps) => {
+                let rewind = emulator.rewind.as_ref().unwrap();
+                format!(
+                    "Rewound {} capture(s), {} left ({} KiB)",
+                    steps,
+                    rewind.len(),
+                    rewind.memory() / 1024
+                )
+            }
+            Err(e) => format!("Failed to rewind: {}", e),
+        }
+    }
+}
+
+pub struct ConfigureRewindCommand {
+    pub interval: u32, // frames between captures
+    pub budget: usize, // MiB
+}
+impl Command for ConfigureRewindCommand {
+    fn execute(&self, emulator: &mut Emulator) -> String {
+        emulator.start_rewind(self.interval, self.budget * 1024 * 1024);
+        format!(
+            "Capturing every {} frame(s) within {} MiB",
+            self.interval.max(1),
+            self.budget
+        )
+    }
+}
+
+// REVERSE EXECUTION
+pub struct StepBackCommand;
+impl Command for StepBackCommand {
+    fn execute(&self, emulator: &mut Emulator) -> String {
+        match reverse::step_back(emulator) {
+            Ok(_) => format!("Stepped back to ${:04X}", emulator.cpu.registers.pc),
+            Err(e) => format!("Failed to step back: {}", e),
+        }
+    }
+}
+
+pub struct ReverseContinueCommand;
+impl Command for ReverseContinueCommand {
+    fn execute(&self, emulator: &mut Emulator) -> String {
+        match reverse::reverse_continue(emulator) {
+            Ok(true) => format!("Breakpoint hit at ${:04X}", emulator.cpu.registers.pc),
+            Ok(false) => "No earlier breakpoint hit, stopped at the oldest capture".to_string(),
+            Err(e) => format!("Failed to reverse: {}", e),
+        }
+    }
+}
+
+// CARTRIDGE CLOCK
+pub struct RtcCommand {
+    pub host_time: bool,
+}
+impl Command for RtcCommand {
+    fn execute(&self, emulator: &mut Emulator) -> String {
+        let clock = match self.host_time {
+            true => "host time",
+            false => "emulated time",
+        };
+        match emulator.set_rtc_host_time(self.host_time) {
+            true => format!("Cartridge clock follows {}", clock),
+            false => format!("Cartridge clocks will follow {}, this one has none", clock),
+        }
+    }
+}
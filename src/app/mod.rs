@@ -1,8 +1,11 @@
 use std::env;
+use std::time::Instant;
 
 pub mod command;
 pub mod tui;
-use crate::emulator::Emulator;
+use crate::debug::runner::{self, DEFAULT_CYCLE_BUDGET, MOONEYE_CYCLE_BUDGET, TestResult};
+use crate::emulator::{CLOCK_SPEED, Emulator};
+use crate::rewind;
 use tui::{EmulatorMode, Tui};
 
 struct EmulatorApp {
@@ -21,6 +24,7 @@ impl EmulatorApp {
     fn new() -> Self {
         let tui = Tui::new();
         let mut emulator = Emulator::new();
+        emulator.start_rewind(rewind::DEFAULT_INTERVAL, rewind::DEFAULT_BUDGET);
         handle_arguments(&mut emulator);
         Self { tui, emulator }
     }
@@ -29,10 +33,112 @@ impl EmulatorApp {
 impl Drop for EmulatorApp {
     fn drop(&mut self) {
         self.tui.shutdown();
+        if let Err(e) = self.emulator.flush_battery() {
+            eprintln!("Failed to write save file: {}", e);
+        }
+    }
+}
+
+// gameboy-rs --test <rom> [cycles] runs a test ROM without the TUI
+fn run_headless(args: &[String]) {
+    let Some(path) = args.get(2) else {
+        eprintln!("usage: gameboy-rs --test <rom> [cycles]");
+        std::process::exit(2);
+    };
+    let budget = args
+        .get(3)
+        .and_then(|c| c.parse().ok())
+        .unwrap_or(DEFAULT_CYCLE_BUDGET);
+
+    // exit status 2 like a usage error, 1 is kept for failing tests
+    let report = match runner::run_test_rom(path, budget) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(2);
+        }
+    };
+    println!("{}", report.output);
+    println!("{}: {}", path, report.summary());
+
+    if report.result != TestResult::Passed {
+        std::process::exit(1);
+    }
+}
+
+// gameboy-rs --mooneye <dir> [cycles] runs every ROM below dir and prints a table
+fn run_mooneye(args: &[String]) {
+    let Some(dir) = args.get(2) else {
+        eprintln!("usage: gameboy-rs --mooneye <dir> [cycles]");
+        std::process::exit(2);
+    };
+    let budget = args
+        .get(3)
+        .and_then(|c| c.parse().ok())
+        .unwrap_or(MOONEYE_CYCLE_BUDGET);
+
+    let reports = match runner::run_mooneye_dir(dir, budget) {
+        Ok(reports) => reports,
+        Err(e) => {
+            eprintln!("{}: {}", dir, e);
+            std::process::exit(2);
+        }
+    };
+    print!("{}", runner::format_table(&reports));
+
+    if reports
+        .iter()
+        .any(|(_, report)| report.result != TestResult::Passed)
+    {
+        std::process::exit(1);
+    }
+}
+
+// gameboy-rs --bench <rom> [cycles] times the interpreter with and without
+// the decoded instruction cache
+fn run_bench(args: &[String]) {
+    let Some(path) = args.get(2) else {
+        eprintln!("usage: gameboy-rs --bench <rom> [cycles]");
+        std::process::exit(2);
+    };
+    let budget: u64 = args
+        .get(3)
+        .and_then(|c| c.parse().ok())
+        .unwrap_or(CLOCK_SPEED * 60);
+
+    for cache in [false, true] {
+        let mut emulator = Emulator::new();
+        if let Err(e) = emulator.load_rom(path) {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(2);
+        }
+        emulator.mmu.code_cache.enabled = cache;
+
+        let start = Instant::now();
+        let mut cycles = 0;
+        while cycles < budget {
+            cycles += emulator.tick() as u64;
+        }
+        let elapsed = start.elapsed().as_secs_f64();
+
+        println!(
+            "cache {:<3}  {:>7.3} s ({:>5.1}x real time)",
+            if cache { "on" } else { "off" },
+            elapsed,
+            budget as f64 / elapsed / CLOCK_SPEED as f64,
+        );
     }
 }
 
 pub fn run() {
+    let args: Vec<String> = env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("--test") => return run_headless(&args),
+        Some("--mooneye") => return run_mooneye(&args),
+        Some("--bench") => return run_bench(&args),
+        _ => {}
+    }
+
     let mut emulator = EmulatorApp::new();
 
     loop {
@@ -52,13 +158,18 @@ impl EmulatorApp {
                 if self.tui.advance {
                     self.emulator.tick();
                     self.tui.advance = false;
+                    // already stopped, don't carry it over into the next run
+                    self.emulator.take_break_request();
                 }
             }
             EmulatorMode::Continuous => {
                 if self.emulator.check_breakpoint() {
-                    self.tui.set_mode(EmulatorMode::Step);
+                    self.tui.set_mode(EmulatorMode::Step, &mut self.emulator);
                 }
                 self.emulator.tick();
+                if self.emulator.take_break_request() {
+                    self.tui.set_mode(EmulatorMode::Step, &mut self.emulator);
+                }
             }
         }
     }
@@ -84,10 +195,8 @@ impl EmulatorApp {
                 }
             }
             EmulatorMode::Continuous => {
-                if self.emulator.draw_call() {
-                    if !self.tui.poll(&mut self.emulator) {
-                        return true;
-                    }
+                if self.emulator.draw_call() && !self.tui.poll(&mut self.emulator) {
+                    return true;
                 }
             }
         }
@@ -14,7 +14,7 @@ impl Widget for InfoView {
         let cpu = &emulator.cpu;
         let mmu = &emulator.mmu;
 
-        let cpu_info = format!(
+        let mut cpu_info = format!(
             "PC: 0x{:04X}\n\
             SP: 0x{:04X}\n\
             A:  0x{:02X}  F: 0x{:02X}\n\
@@ -38,6 +38,10 @@ impl Widget for InfoView {
             cpu.registers.get_flag(Flags::C) as u8,
         );
 
+        if let Some(message) = cpu.lockup_message() {
+            cpu_info.push_str(&format!("\n{}", message));
+        }
+
         let instruction_info = if let Some(cart) = &mmu.cartridge {
             format!(
                 "Byte:      0x{:02X}\n\
@@ -48,8 +52,8 @@ impl Widget for InfoView {
                 RAM Size: {} KB\n\
                 MBC Type: {}",
                 mmu.read_8(cpu.registers.pc),
-                mmu.read_8(cpu.registers.pc + 1),
-                mmu.read_8(cpu.registers.pc + 2),
+                mmu.read_8(cpu.registers.pc.wrapping_add(1)),
+                mmu.read_8(cpu.registers.pc.wrapping_add(2)),
                 disassemble(&cpu.get_current_opcode(mmu).opcode, mmu, cpu),
                 cart.rom.len() / 1024,
                 cart.ram.len() / 1024,
@@ -60,10 +64,12 @@ impl Widget for InfoView {
         };
 
         // let ppu_info = format!("Complete: {}\n", mmu.ppu.complete);
-        let ppu_info = format!("Complete: debug\n");
+        let ppu_info = "Complete: debug\n".to_string();
 
         let serial_output = &mmu.serial.output;
 
+        let call_stack = emulator.backtrace().join("\n");
+
         let outer = Block::default();
 
         frame.render_widget(&outer, area);
@@ -71,7 +77,11 @@ impl Widget for InfoView {
 
         let rows = Layout::default()
             .direction(Direction::Vertical)
-            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .constraints([
+                Constraint::Percentage(40),
+                Constraint::Percentage(35),
+                Constraint::Percentage(25),
+            ])
             .split(inner);
 
         let row0 = Layout::default()
@@ -106,6 +116,12 @@ impl Widget for InfoView {
             Paragraph::new(ppu_info).block(Block::default().title("PPU").borders(Borders::ALL)),
             row1[1],
         );
+
+        frame.render_widget(
+            Paragraph::new(call_stack)
+                .block(Block::default().title("Call stack").borders(Borders::ALL)),
+            rows[2],
+        );
     }
 
     fn handle_key(&mut self, _key: crossterm::event::KeyEvent, _emulator: &mut Emulator) -> bool {
@@ -2,6 +2,7 @@ mod debug;
 mod ppu;
 mod shell;
 use crate::app::tui::debug::DebugView;
+use crate::debug::reverse;
 use crate::emulator::Emulator;
 use crossterm::event::KeyEvent;
 use crossterm::execute;
@@ -27,6 +28,21 @@ pub trait View {
     fn handle_key(&mut self, _key: KeyEvent, _emulator: &mut Emulator) -> bool {
         false
     }
+
+    // called once per emulated frame while running continuously
+    fn frame(&mut self, _emulator: &mut Emulator) {}
+
+    // the emulator stopped running continuously, `frame` won't be called
+    // until it runs again
+    fn paused(&mut self, _emulator: &mut Emulator) {}
+
+    // lets a view switch the emulator mode after handling a key
+    fn take_mode_request(&mut self) -> Option<EmulatorMode> {
+        None
+    }
+
+    // outcome of a global key, the shell keeps it in its history
+    fn report(&mut self, _message: &str) {}
 }
 
 pub struct Tui {
@@ -38,6 +54,8 @@ pub struct Tui {
 }
 
 impl Tui {
+    // takes over the terminal, not something to do by default
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
         enable_raw_mode().unwrap();
         let mut stdout = std::io::stdout();
@@ -62,7 +80,12 @@ impl Tui {
         self.views[self.active].draw(&mut self.terminal, emulator);
     }
 
-    pub fn set_mode(&mut self, mode: EmulatorMode) {
+    pub fn set_mode(&mut self, mode: EmulatorMode, emulator: &mut Emulator) {
+        if self.emulator_mode == EmulatorMode::Continuous && mode == EmulatorMode::Step {
+            for view in &mut self.views {
+                view.paused(emulator);
+            }
+        }
         self.emulator_mode = mode;
     }
 
@@ -71,6 +94,13 @@ impl Tui {
     }
 
     pub fn poll(&mut self, emulator: &mut Emulator) -> bool {
+        // in continuous mode polling happens once per frame
+        if self.emulator_mode == EmulatorMode::Continuous {
+            for view in &mut self.views {
+                view.frame(emulator);
+            }
+        }
+
         if !event::poll(Duration::from_millis(16)).unwrap() {
             return true;
         }
@@ -84,15 +114,19 @@ impl Tui {
         }
 
         if self.views[self.active].handle_key(key, emulator) {
+            if let Some(mode) = self.views[self.active].take_mode_request() {
+                self.set_mode(mode, emulator);
+            }
             return true;
         }
 
         match key.code {
             KeyCode::F(1) => {
-                self.emulator_mode = match &self.emulator_mode {
+                let mode = match &self.emulator_mode {
                     EmulatorMode::Continuous => EmulatorMode::Step,
                     EmulatorMode::Step => EmulatorMode::Continuous,
-                }
+                };
+                self.set_mode(mode, emulator);
             }
             KeyCode::Tab => {
                 self.active = (self.active + 1) % self.views.len();
@@ -100,17 +134,32 @@ impl Tui {
             KeyCode::Esc => {
                 return false;
             }
-            KeyCode::Right => {
-                if self.emulator_mode == EmulatorMode::Step {
-                    self.advance = true
+            KeyCode::Right if self.emulator_mode == EmulatorMode::Step => {
+                self.advance = true;
+            }
+            KeyCode::Left if self.emulator_mode == EmulatorMode::Step => {
+                if let Err(e) = reverse::step_back(emulator) {
+                    self.report(&format!("Failed to step back: {}", e));
                 }
             }
+            // hold to keep going back, the game stays paused afterwards
+            KeyCode::F(3) => match emulator.rewind(1) {
+                Ok(0) => self.report("Nothing left to rewind"),
+                Ok(_) => self.set_mode(EmulatorMode::Step, emulator),
+                Err(e) => self.report(&format!("Failed to rewind: {}", e)),
+            },
             _ => {}
         }
 
         true
     }
 
+    fn report(&mut self, message: &str) {
+        for view in &mut self.views {
+            view.report(message);
+        }
+    }
+
     pub fn shutdown(&mut self) {
         disable_raw_mode().unwrap();
         execute!(self.terminal.backend_mut(), LeaveAlternateScreen).unwrap();
@@ -0,0 +1,141 @@
+use std::fs;
+
+use crossterm::event::KeyCode;
+
+use crate::joypad::Button;
+
+// frames a button stays down after its key was reported
+const HOLD_FRAMES: u32 = 6;
+
+// Keys to Game Boy buttons, read from a file with one "key = button" per line:
+//
+//   up = up
+//   x = a
+//   enter = start
+//   hold = 8
+//
+// Keys are single characters or up, down, left, right, enter, backspace,
+// space and tab. Buttons are a, b, start, select, up, down, left and right.
+//
+// Terminals only report key presses, never releases, so every press holds
+// its button for `hold` frames. Key repeat of a held key keeps it down.
+pub struct Controls {
+    bindings: Vec<(KeyCode, Button)>,
+    pub hold_frames: u32,
+}
+
+impl Controls {
+    pub fn new() -> Self {
+        Self {
+            bindings: vec![
+                (KeyCode::Up, Button::Up),
+                (KeyCode::Down, Button::Down),
+                (KeyCode::Left, Button::Left),
+                (KeyCode::Right, Button::Right),
+                (KeyCode::Char('x'), Button::A),
+                (KeyCode::Char('z'), Button::B),
+                (KeyCode::Enter, Button::Start),
+                (KeyCode::Backspace, Button::Select),
+            ],
+            hold_frames: HOLD_FRAMES,
+        }
+    }
+
+    // the default mapping with the bindings of the file on top, a missing
+    // file just keeps the defaults
+    pub fn load(path: &str) -> Self {
+        let mut controls = Self::new();
+        if let Ok(text) = fs::read_to_string(path) {
+            controls.apply(&text);
+        }
+        controls
+    }
+
+    pub fn apply(&mut self, text: &str) {
+        for line in text.lines() {
+            let Some((key, button)) = line.split_once('=') else {
+                continue;
+            };
+            if key.trim() == "hold" {
+                if let Ok(frames) = button.trim().parse() {
+                    self.hold_frames = frames;
+                }
+                continue;
+            }
+            if let (Some(key), Some(button)) = (parse_key(key.trim()), parse_button(button.trim()))
+            {
+                self.bind(key, button);
+            }
+        }
+    }
+
+    pub fn bind(&mut self, key: KeyCode, button: Button) {
+        self.bindings.retain(|(k, _)| *k != key);
+        self.bindings.push((key, button));
+    }
+
+    pub fn button(&self, key: KeyCode) -> Option<Button> {
+        // letters match regardless of shift and caps lock
+        let key = match key {
+            KeyCode::Char(c) => KeyCode::Char(c.to_ascii_lowercase()),
+            key => key,
+        };
+        self.bindings
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, button)| *button)
+    }
+}
+
+fn parse_key(key: &str) -> Option<KeyCode> {
+    let key = key.to_ascii_lowercase();
+    match key.as_str() {
+        "up" => Some(KeyCode::Up),
+        "down" => Some(KeyCode::Down),
+        "left" => Some(KeyCode::Left),
+        "right" => Some(KeyCode::Right),
+        "enter" => Some(KeyCode::Enter),
+        "backspace" => Some(KeyCode::Backspace),
+        "space" => Some(KeyCode::Char(' ')),
+        "tab" => Some(KeyCode::Tab),
+        _ => {
+            let mut chars = key.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Some(KeyCode::Char(c)),
+                _ => None,
+            }
+        }
+    }
+}
+
+fn parse_button(button: &str) -> Option<Button> {
+    match button.to_ascii_lowercase().as_str() {
+        "a" => Some(Button::A),
+        "b" => Some(Button::B),
+        "start" => Some(Button::Start),
+        "select" => Some(Button::Select),
+        "up" => Some(Button::Up),
+        "down" => Some(Button::Down),
+        "left" => Some(Button::Left),
+        "right" => Some(Button::Right),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_bindings_override_defaults() {
+        let mut controls = Controls::new();
+        assert_eq!(controls.button(KeyCode::Char('X')), Some(Button::A));
+
+        controls.apply("x = b\nk=A\nnonsense\nenter = nothing\nhold = 10\n");
+        assert_eq!(controls.button(KeyCode::Char('x')), Some(Button::B));
+        assert_eq!(controls.button(KeyCode::Char('k')), Some(Button::A));
+        assert_eq!(controls.button(KeyCode::Enter), Some(Button::Start));
+        assert_eq!(controls.button(KeyCode::Char('q')), None);
+        assert_eq!(controls.hold_frames, 10);
+    }
+}
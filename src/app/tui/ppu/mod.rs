@@ -1,15 +1,43 @@
+mod controls;
+
+use controls::Controls;
+use crossterm::event::{KeyCode, KeyEvent};
 use ratatui::prelude::*;
+use ratatui::widgets::block::Title;
 use ratatui::widgets::{Block, Borders};
 use std::io::Stdout;
 
-use crate::app::tui::View;
+use crate::app::tui::{EmulatorMode, View};
 use crate::emulator::Emulator;
-
-pub struct PpuView;
+use crate::joypad::Button;
+
+// optional key bindings, see `Controls`
+const CONTROLS_FILE: &str = "keys.cfg";
+
+pub struct PpuView {
+    playing: bool,
+    controls: Controls,
+    // buttons held down and the frames left until they are released
+    held: Vec<(Button, u32)>,
+    mode_request: Option<EmulatorMode>,
+    rumbling: bool,
+}
 
 impl PpuView {
     pub fn new() -> Self {
-        Self
+        Self {
+            playing: false,
+            controls: Controls::load(CONTROLS_FILE),
+            held: Vec::new(),
+            mode_request: None,
+            rumbling: false,
+        }
+    }
+
+    fn release_all(&mut self, emulator: &mut Emulator) {
+        for (button, _) in self.held.drain(..) {
+            emulator.release(button);
+        }
     }
 }
 
@@ -28,7 +56,15 @@ impl View for PpuView {
             .draw(|frame| {
                 let area = frame.size();
 
-                let block = Block::default().title(" PPU ").borders(Borders::ALL);
+                let title = if self.playing {
+                    " PPU - playing (F2 to stop) "
+                } else {
+                    " PPU - F2 to play "
+                };
+                let mut block = Block::default().title(title).borders(Borders::ALL);
+                if self.rumbling {
+                    block = block.title(Title::from(" RUMBLE ").alignment(Alignment::Right));
+                }
 
                 let inner = block.inner(area);
                 frame.render_widget(block, area);
@@ -55,8 +91,8 @@ impl View for PpuView {
                         let fg = gb_color(top);
                         let bg = gb_color(bottom);
 
-                        let px = inner.x + x as u16;
-                        let py = inner.y + ty as u16;
+                        let px = inner.x + x;
+                        let py = inner.y + ty;
 
                         buf.get_mut(px, py)
                             .set_symbol(ch)
@@ -67,7 +103,114 @@ impl View for PpuView {
             .unwrap();
     }
 
-    fn handle_key(&mut self, _key: crossterm::event::KeyEvent, _emu: &mut Emulator) -> bool {
-        false
+    fn handle_key(&mut self, key: KeyEvent, emulator: &mut Emulator) -> bool {
+        if key.code == KeyCode::F(2) {
+            self.playing = !self.playing;
+            if self.playing {
+                self.mode_request = Some(EmulatorMode::Continuous);
+            } else {
+                self.release_all(emulator);
+            }
+            return true;
+        }
+
+        if !self.playing {
+            return false;
+        }
+        let Some(button) = self.controls.button(key.code) else {
+            return false;
+        };
+
+        let frames = self.controls.hold_frames;
+        match self.held.iter_mut().find(|(b, _)| *b == button) {
+            // key repeat keeps the button down
+            Some((_, left)) => *left = frames,
+            None => {
+                emulator.press(button);
+                self.held.push((button, frames));
+            }
+        }
+        true
+    }
+
+    fn frame(&mut self, emulator: &mut Emulator) {
+        for (button, left) in &mut self.held {
+            *left = left.saturating_sub(1);
+            if *left == 0 {
+                emulator.release(*button);
+            }
+        }
+        self.held.retain(|(_, left)| *left > 0);
+
+        if let Some(on) = emulator.take_rumble() {
+            self.rumbling = on;
+        }
+    }
+
+    // frames no longer count down, nothing would ever be released
+    fn paused(&mut self, emulator: &mut Emulator) {
+        self.release_all(emulator);
+    }
+
+    fn take_mode_request(&mut self) -> Option<EmulatorMode> {
+        self.mode_request.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::KeyModifiers;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn presses_are_held_for_the_configured_frames() {
+        let mut emulator = Emulator::new();
+        let mut view = PpuView {
+            playing: false,
+            controls: Controls::new(),
+            held: Vec::new(),
+            mode_request: None,
+            rumbling: false,
+        };
+        view.controls.hold_frames = 2;
+
+        // keys only reach the game in play mode, which runs continuously
+        assert!(!view.handle_key(key(KeyCode::Char('x')), &mut emulator));
+        assert!(view.handle_key(key(KeyCode::F(2)), &mut emulator));
+        assert!(view.take_mode_request() == Some(EmulatorMode::Continuous));
+
+        assert!(view.handle_key(key(KeyCode::Char('x')), &mut emulator));
+        assert!(emulator.mmu.joypad.is_pressed(Button::A));
+        view.frame(&mut emulator);
+        assert!(emulator.mmu.joypad.is_pressed(Button::A));
+        view.frame(&mut emulator);
+        assert!(!emulator.mmu.joypad.is_pressed(Button::A));
+
+        // leaving play mode lets go of everything
+        view.handle_key(key(KeyCode::Enter), &mut emulator);
+        view.handle_key(key(KeyCode::F(2)), &mut emulator);
+        assert!(!emulator.mmu.joypad.is_pressed(Button::Start));
+    }
+
+    #[test]
+    fn pausing_releases_held_buttons() {
+        let mut emulator = Emulator::new();
+        let mut view = PpuView {
+            playing: true,
+            controls: Controls::new(),
+            held: Vec::new(),
+            mode_request: None,
+            rumbling: false,
+        };
+        view.handle_key(key(KeyCode::Char('x')), &mut emulator);
+        assert!(emulator.mmu.joypad.is_pressed(Button::A));
+
+        view.paused(&mut emulator);
+        assert!(!emulator.mmu.joypad.is_pressed(Button::A));
+        assert!(view.held.is_empty());
     }
 }
@@ -2,10 +2,14 @@ use std::env;
 use std::io::Stdout;
 
 use crate::app::command::{
-    AddBreakpointCommand, Command, DumpInstructionsCommand, LoadRomCommand, ResetCommand,
-    ToggleLogCommand,
+    AddBreakpointCommand, BacktraceCommand, Command, ConfigureRewindCommand,
+    DumpInstructionsCommand, LoadRomCommand, LoadStateCommand, LoadSymbolsCommand,
+    PlayMovieCommand, RecordMovieCommand, ResetCommand, ReverseContinueCommand, RewindCommand,
+    RtcCommand, SaveProfileCommand, SaveStateCommand, StartTraceCommand, StepBackCommand,
+    StopMovieCommand, StopTraceCommand, ToggleCodeCacheCommand, ToggleLogCommand,
+    ToggleMCycleCommand, ToggleProfilerCommand, ToggleTrapCommand,
 };
-use crate::app::tui::View;
+use crate::app::tui::{EmulatorMode, View};
 use crate::emulator::Emulator;
 use crossterm::event::{KeyCode, KeyEvent};
 use ratatui::layout::{Constraint, Direction, Layout};
@@ -15,6 +19,7 @@ use ratatui::widgets::Paragraph;
 pub struct ShellView {
     buffer: String,
     history: Vec<String>,
+    mode_request: Option<EmulatorMode>,
 }
 
 impl View for ShellView {
@@ -71,6 +76,14 @@ impl View for ShellView {
             _ => false,
         }
     }
+
+    fn take_mode_request(&mut self) -> Option<EmulatorMode> {
+        self.mode_request.take()
+    }
+
+    fn report(&mut self, message: &str) {
+        self.history.push(message.to_string());
+    }
 }
 
 impl ShellView {
@@ -78,6 +91,7 @@ impl ShellView {
         Self {
             buffer: String::new(),
             history: vec![],
+            mode_request: None,
         }
     }
 
@@ -105,6 +119,24 @@ impl ShellView {
                 self.history.push(ToggleLogCommand.execute(emulator));
             }
 
+            "trace" if parts.len() == 2 => {
+                let path = parts[1].to_string();
+                self.history
+                    .push(StartTraceCommand { path }.execute(emulator));
+            }
+
+            "trace" => {
+                self.history.push(StopTraceCommand.execute(emulator));
+            }
+
+            "mcycle" => {
+                self.history.push(ToggleMCycleCommand.execute(emulator));
+            }
+
+            "cache" => {
+                self.history.push(ToggleCodeCacheCommand.execute(emulator));
+            }
+
             "dump" if parts.len() == 2 => {
                 let path = parts[1].to_string();
                 self.history
@@ -119,6 +151,97 @@ impl ShellView {
                     .push(AddBreakpointCommand { address }.execute(emulator));
             }
 
+            "profile" if parts.len() == 2 => {
+                let path = parts[1].to_string();
+                self.history
+                    .push(SaveProfileCommand { path }.execute(emulator));
+            }
+
+            "profile" => {
+                // the report spans several lines
+                let output = ToggleProfilerCommand.execute(emulator);
+                self.history.extend(output.lines().map(String::from));
+            }
+
+            "bt" => {
+                let output = BacktraceCommand.execute(emulator);
+                self.history.extend(output.lines().map(String::from));
+            }
+
+            "sym" if parts.len() == 2 => {
+                let path = parts[1].to_string();
+                self.history
+                    .push(LoadSymbolsCommand { path }.execute(emulator));
+            }
+
+            "record" if parts.len() == 2 => {
+                let path = parts[1].to_string();
+                self.history
+                    .push(RecordMovieCommand { path }.execute(emulator));
+            }
+
+            "play" if parts.len() == 2 => {
+                let path = parts[1].to_string();
+                self.history
+                    .push(PlayMovieCommand { path }.execute(emulator));
+            }
+
+            "record" | "play" => {
+                self.history.push(StopMovieCommand.execute(emulator));
+            }
+
+            "save" if parts.len() == 2 => {
+                let target = parts[1].to_string();
+                self.history
+                    .push(SaveStateCommand { target }.execute(emulator));
+            }
+
+            "loadstate" if parts.len() == 2 => {
+                let target = parts[1].to_string();
+                self.history
+                    .push(LoadStateCommand { target }.execute(emulator));
+            }
+
+            "rewind" if parts.len() == 4 && parts[1] == "every" => {
+                match (parts[2].parse(), parts[3].parse()) {
+                    (Ok(interval), Ok(budget)) => self
+                        .history
+                        .push(ConfigureRewindCommand { interval, budget }.execute(emulator)),
+                    _ => self
+                        .history
+                        .push("Usage: rewind every <frames> <MiB>".into()),
+                }
+            }
+
+            "rewind" if parts.len() <= 2 => {
+                let steps = parts.get(1).and_then(|s| s.parse().ok()).unwrap_or(1);
+                self.history.push(RewindCommand { steps }.execute(emulator));
+            }
+
+            // both leave the emulator paused where they stopped
+            "step-back" => {
+                self.history.push(StepBackCommand.execute(emulator));
+                self.mode_request = Some(EmulatorMode::Step);
+            }
+
+            "reverse-continue" => {
+                self.history.push(ReverseContinueCommand.execute(emulator));
+                self.mode_request = Some(EmulatorMode::Step);
+            }
+
+            "rtc" if parts.len() == 2 => match parts[1] {
+                "host" | "emulated" => {
+                    let host_time = parts[1] == "host";
+                    self.history
+                        .push(RtcCommand { host_time }.execute(emulator));
+                }
+                _ => self.history.push("Usage: rtc host|emulated".into()),
+            },
+
+            "trap" => {
+                self.history.push(ToggleTrapCommand.execute(emulator));
+            }
+
             _ => {
                 self.history.push("Unknown command".into());
             }
@@ -1,5 +1,9 @@
 pub mod channels;
 
+use std::io::Error;
+
+use crate::state::{SaveState, StateReader, StateWriter};
+
 pub struct Apu {}
 
 impl Apu {
@@ -13,3 +17,18 @@ impl Apu {
 
     pub fn write_reg(&mut self, _addr: u16, _value: u8) {}
 }
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// nothing to keep until the channels are emulated
+impl SaveState for Apu {
+    fn save_state(&self, _state: &mut StateWriter) {}
+
+    fn load_state(&mut self, _state: &mut StateReader) -> Result<(), Error> {
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+use crate::cpu::decoder::DecodedInstruction;
+use crate::interrupt_controller::Interrupt;
+
+// The memory side of the CPU. The CPU only talks to the rest of the machine
+// through this trait, so it can run against the full `Mmu` or against
+// something simpler, like a flat 64 KiB test memory.
+//
+// Every `cpu_*` call is one M-cycle of the instruction, an implementation
+// that wants per-access timing advances its peripherals there.
+pub trait Bus {
+    fn cpu_read_8(&mut self, addr: u16) -> u8;
+    fn cpu_write_8(&mut self, addr: u16, value: u8);
+
+    fn cpu_read_16(&mut self, addr: u16) -> u16 {
+        let lo = self.cpu_read_8(addr) as u16;
+        let hi = self.cpu_read_8(addr.wrapping_add(1)) as u16;
+        hi << 8 | lo
+    }
+
+    // internal CPU cycle without a memory access
+    fn cpu_idle(&mut self);
+
+    // reads memory without side effects or timing
+    fn peek(&self, addr: u16) -> u8;
+
+    // IE & IF
+    fn pending_interrupts(&self) -> u8;
+    fn highest_interrupt(&self) -> Option<Interrupt>;
+    fn ack_interrupt(&mut self, irq: Interrupt);
+
+    // low nibble of P1, a 0 bit is a pressed button on a selected line
+    fn joypad_lines(&self) -> u8;
+
+    // instruction at addr decoded ahead of time, for buses that keep a code
+    // cache. Fetching it this way performs no memory reads
+    fn cached_instruction(&mut self, _addr: u16) -> Option<DecodedInstruction> {
+        None
+    }
+
+    // ROM bank mapped at addr, lets debug tools tell banked code apart
+    fn rom_bank(&self, _addr: u16) -> u16 {
+        0
+    }
+
+    // STOP side effects: resets DIV and performs an armed CGB speed switch,
+    // returns whether the speed was switched
+    fn stop(&mut self) -> bool;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Access {
+    Read(u16, u8),
+    Write(u16, u8),
+    Idle,
+}
+
+// Wraps another bus and records every CPU access going through it.
+pub struct RecordingBus<B: Bus> {
+    pub inner: B,
+    pub accesses: Vec<Access>,
+}
+
+impl<B: Bus> RecordingBus<B> {
+    pub fn new(inner: B) -> Self {
+        Self {
+            inner,
+            accesses: Vec::new(),
+        }
+    }
+}
+
+impl<B: Bus> Bus for RecordingBus<B> {
+    fn cpu_read_8(&mut self, addr: u16) -> u8 {
+        let value = self.inner.cpu_read_8(addr);
+        self.accesses.push(Access::Read(addr, value));
+        value
+    }
+
+    fn cpu_write_8(&mut self, addr: u16, value: u8) {
+        self.inner.cpu_write_8(addr, value);
+        self.accesses.push(Access::Write(addr, value));
+    }
+
+    fn cpu_idle(&mut self) {
+        self.inner.cpu_idle();
+        self.accesses.push(Access::Idle);
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.inner.peek(addr)
+    }
+
+    fn pending_interrupts(&self) -> u8 {
+        self.inner.pending_interrupts()
+    }
+
+    fn highest_interrupt(&self) -> Option<Interrupt> {
+        self.inner.highest_interrupt()
+    }
+
+    fn ack_interrupt(&mut self, irq: Interrupt) {
+        self.inner.ack_interrupt(irq);
+    }
+
+    fn joypad_lines(&self) -> u8 {
+        self.inner.joypad_lines()
+    }
+
+    fn rom_bank(&self, addr: u16) -> u16 {
+        self.inner.rom_bank(addr)
+    }
+
+    fn stop(&mut self) -> bool {
+        self.inner.stop()
+    }
+}
@@ -0,0 +1,228 @@
+use std::io::Error;
+
+use crate::debug::Location;
+use crate::state::{SaveState, StateReader, StateWriter, invalid};
+
+// deeper chains are most likely frames that were never returned from
+const MAX_FRAMES: usize = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrameKind {
+    Call = 0,
+    Rst = 1,
+    Interrupt = 2,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frame {
+    pub kind: FrameKind,
+    pub function: Location,
+    // address of the CALL/RST, or the interrupted PC
+    pub caller: u16,
+    pub return_addr: u16,
+    // SP right after the return address was pushed
+    pub sp: u16,
+}
+
+// Shadow of the frames the program pushed on its stack. The real stack is
+// plain memory that code is free to rewrite, so every return is checked
+// against the frame it should pop and mismatches are reported
+pub struct CallStack {
+    pub frames: Vec<Frame>,
+    pub warning: Option<String>,
+}
+
+impl CallStack {
+    pub fn new() -> Self {
+        Self {
+            frames: Vec::new(),
+            warning: None,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.warning = None;
+    }
+
+    pub fn push(&mut self, frame: Frame) {
+        // frames at or below the new SP were released without a return,
+        // SP got reloaded or the return address was popped by hand
+        let live = self.frames.iter().take_while(|f| f.sp > frame.sp).count();
+        if live < self.frames.len() {
+            self.warning = Some(format!(
+                "{} frame(s) abandoned before call at ${:04X}",
+                self.frames.len() - live,
+                frame.caller
+            ));
+            self.frames.truncate(live);
+        }
+
+        if self.frames.len() == MAX_FRAMES {
+            self.frames.remove(0);
+        }
+        self.frames.push(frame);
+    }
+
+    // `sp` is the stack pointer the return address was popped from
+    pub fn ret(&mut self, pc: u16, target: u16, sp: u16) {
+        let matches = |f: &Frame| f.return_addr == target && f.sp == sp;
+
+        if self.frames.last().is_some_and(matches) {
+            self.frames.pop();
+            return;
+        }
+
+        match self.frames.iter().rposition(matches) {
+            Some(index) => {
+                self.warning = Some(format!(
+                    "RET at ${:04X} unwound {} frame(s)",
+                    pc,
+                    self.frames.len() - index
+                ));
+                self.frames.truncate(index);
+            }
+            None => {
+                // PUSH + RET used as a jump, or a rewritten return address
+                self.warning = Some(format!(
+                    "RET at ${:04X} to ${:04X} matches no frame",
+                    pc, target
+                ));
+            }
+        }
+    }
+}
+
+impl Default for CallStack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Saved with the CPU so that backtraces survive rewinding and stepping back
+impl SaveState for CallStack {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u16(self.frames.len() as u16);
+        for frame in &self.frames {
+            state.u8(frame.kind as u8);
+            state.u16(frame.function.bank);
+            state.u16(frame.function.addr);
+            state.u16(frame.caller);
+            state.u16(frame.return_addr);
+            state.u16(frame.sp);
+        }
+        state.block(self.warning.as_deref().unwrap_or_default().as_bytes());
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Error> {
+        let count = state.u16()? as usize;
+        if count > MAX_FRAMES {
+            return Err(invalid("too many call stack frames"));
+        }
+        self.frames.clear();
+        for _ in 0..count {
+            let kind = match state.u8()? {
+                0 => FrameKind::Call,
+                1 => FrameKind::Rst,
+                2 => FrameKind::Interrupt,
+                _ => return Err(invalid("invalid call stack frame")),
+            };
+            self.frames.push(Frame {
+                kind,
+                function: Location {
+                    bank: state.u16()?,
+                    addr: state.u16()?,
+                },
+                caller: state.u16()?,
+                return_addr: state.u16()?,
+                sp: state.u16()?,
+            });
+        }
+        let warning = String::from_utf8_lossy(state.block()?);
+        self.warning = (!warning.is_empty()).then(|| warning.into_owned());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(return_addr: u16, sp: u16) -> Frame {
+        Frame {
+            kind: FrameKind::Call,
+            function: Location {
+                bank: 0,
+                addr: 0x2000,
+            },
+            caller: return_addr.wrapping_sub(3),
+            return_addr,
+            sp,
+        }
+    }
+
+    #[test]
+    fn balanced_calls_leave_no_warning() {
+        let mut stack = CallStack::new();
+        stack.push(frame(0x0153, 0xFFFC));
+        stack.push(frame(0x2003, 0xFFFA));
+        stack.ret(0x3000, 0x2003, 0xFFFA);
+        stack.ret(0x2005, 0x0153, 0xFFFC);
+        assert!(stack.frames.is_empty());
+        assert_eq!(stack.warning, None);
+    }
+
+    #[test]
+    fn return_past_several_frames_unwinds_them() {
+        let mut stack = CallStack::new();
+        stack.push(frame(0x0153, 0xFFFC));
+        stack.push(frame(0x2003, 0xFFFA));
+        stack.push(frame(0x3003, 0xFFF8));
+        // SP reloaded to the outermost frame before returning
+        stack.ret(0x4000, 0x0153, 0xFFFC);
+        assert!(stack.frames.is_empty());
+        assert!(stack.warning.is_some());
+    }
+
+    #[test]
+    fn return_to_unknown_address_keeps_frames() {
+        let mut stack = CallStack::new();
+        stack.push(frame(0x0153, 0xFFFC));
+        // PUSH HL; RET jump table
+        stack.ret(0x2000, 0x1234, 0xFFFA);
+        assert_eq!(stack.frames.len(), 1);
+        assert!(stack.warning.is_some());
+    }
+
+    #[test]
+    fn calls_drop_frames_released_without_return() {
+        let mut stack = CallStack::new();
+        stack.push(frame(0x0153, 0xFFFC));
+        stack.push(frame(0x2003, 0xFFFA));
+        // return address popped into a register, then a call reuses the slot
+        stack.push(frame(0x2103, 0xFFFA));
+        assert_eq!(stack.frames.len(), 2);
+        assert_eq!(stack.frames[1].return_addr, 0x2103);
+        assert!(stack.warning.is_some());
+    }
+
+    #[test]
+    fn frames_survive_a_save_state() {
+        let mut stack = CallStack::new();
+        stack.push(frame(0x0153, 0xFFFC));
+        stack.push(Frame {
+            kind: FrameKind::Interrupt,
+            ..frame(0x2003, 0xFFFA)
+        });
+        stack.ret(0x2000, 0x1234, 0xFFF8);
+
+        let mut state = StateWriter::new();
+        stack.save_state(&mut state);
+        let mut loaded = CallStack::new();
+        loaded
+            .load_state(&mut StateReader::new(&state.data))
+            .unwrap();
+        assert_eq!(loaded.frames, stack.frames);
+        assert_eq!(loaded.warning, stack.warning);
+    }
+}
@@ -165,7 +165,7 @@ static LOOKUP: [OpcodeEntry; 256] = [
         opcode: Opcode::JrCCE8(CC::NZ),
         length: 2,
         cycles: 8,
-    }, // 0x20 JR NZ,r8 (not taken: 8)
+    }, // 0x20 JR NZ,r8 (taken: 12, not taken: 8)
     OpcodeEntry {
         opcode: Opcode::LdR16N16(R16::HL),
         length: 3,
@@ -205,7 +205,7 @@ static LOOKUP: [OpcodeEntry; 256] = [
         opcode: Opcode::JrCCE8(CC::Z),
         length: 2,
         cycles: 8,
-    }, // 0x28 JR Z,r8 (not taken: 8)
+    }, // 0x28 JR Z,r8 (taken: 12, not taken: 8)
     OpcodeEntry {
         opcode: Opcode::AddHLR16(R16::HL),
         length: 1,
@@ -245,7 +245,7 @@ static LOOKUP: [OpcodeEntry; 256] = [
         opcode: Opcode::JrCCE8(CC::NC),
         length: 2,
         cycles: 8,
-    }, // 0x30 JR NC,r8 (not taken: 8)
+    }, // 0x30 JR NC,r8 (taken: 12, not taken: 8)
     OpcodeEntry {
         opcode: Opcode::LdSPN16,
         length: 3,
@@ -285,7 +285,7 @@ static LOOKUP: [OpcodeEntry; 256] = [
         opcode: Opcode::JrCCE8(CC::C),
         length: 2,
         cycles: 8,
-    }, // 0x38 JR C,r8 (not taken: 8)
+    }, // 0x38 JR C,r8 (taken: 12, not taken: 8)
     OpcodeEntry {
         opcode: Opcode::AddHLSP,
         length: 1,
@@ -971,7 +971,7 @@ static LOOKUP: [OpcodeEntry; 256] = [
         opcode: Opcode::RetCC(CC::NZ),
         length: 1,
         cycles: 8,
-    }, // 0xC0 RET NZ (not taken: 8)
+    }, // 0xC0 RET NZ (taken: 20, not taken: 8)
     OpcodeEntry {
         opcode: Opcode::PopR16(R16::BC),
         length: 1,
@@ -981,7 +981,7 @@ static LOOKUP: [OpcodeEntry; 256] = [
         opcode: Opcode::JpCCN16(CC::NZ),
         length: 3,
         cycles: 12,
-    }, // 0xC2 JP NZ,nn (not taken: 12)
+    }, // 0xC2 JP NZ,nn (taken: 16, not taken: 12)
     OpcodeEntry {
         opcode: Opcode::JpN16,
         length: 3,
@@ -991,7 +991,7 @@ static LOOKUP: [OpcodeEntry; 256] = [
         opcode: Opcode::CallCCN16(CC::NZ),
         length: 3,
         cycles: 12,
-    }, // 0xC4 CALL NZ,nn (not taken: 12)
+    }, // 0xC4 CALL NZ,nn (taken: 24, not taken: 12)
     OpcodeEntry {
         opcode: Opcode::PushR16(R16::BC),
         length: 1,
@@ -1000,8 +1000,8 @@ static LOOKUP: [OpcodeEntry; 256] = [
     OpcodeEntry {
         opcode: Opcode::AddAN8,
         length: 2,
-        cycles: 16,
-    }, // 0xC6 ADD A,n8  (note: 0xC6 is ADD A,n8 - adjust if naming different)
+        cycles: 8,
+    }, // 0xC6 ADD A,n8
     OpcodeEntry {
         opcode: Opcode::Rst(0x00),
         length: 1,
@@ -1011,7 +1011,7 @@ static LOOKUP: [OpcodeEntry; 256] = [
         opcode: Opcode::RetCC(CC::Z),
         length: 1,
         cycles: 8,
-    }, // 0xC8 RET Z (not taken: 8)
+    }, // 0xC8 RET Z (taken: 20, not taken: 8)
     OpcodeEntry {
         opcode: Opcode::Ret,
         length: 1,
@@ -1021,17 +1021,17 @@ static LOOKUP: [OpcodeEntry; 256] = [
         opcode: Opcode::JpCCN16(CC::Z),
         length: 3,
         cycles: 12,
-    }, // 0xCA JP Z,nn
+    }, // 0xCA JP Z,nn (taken: 16, not taken: 12)
     OpcodeEntry {
         opcode: Opcode::Prefix,
         length: 1,
         cycles: 4,
-    }, // 0xCB CB prefix (we will handle via CB_LOOKUP) - treat as a placeholder
+    }, // 0xCB CB prefix (cycles come from the CB_LOOKUP entry)
     OpcodeEntry {
         opcode: Opcode::CallCCN16(CC::Z),
         length: 3,
         cycles: 12,
-    }, // 0xCC CALL Z,nn
+    }, // 0xCC CALL Z,nn (taken: 24, not taken: 12)
     OpcodeEntry {
         opcode: Opcode::CallN16,
         length: 3,
@@ -1051,7 +1051,7 @@ static LOOKUP: [OpcodeEntry; 256] = [
         opcode: Opcode::RetCC(CC::NC),
         length: 1,
         cycles: 8,
-    }, // 0xD0 RET NC
+    }, // 0xD0 RET NC (taken: 20, not taken: 8)
     OpcodeEntry {
         opcode: Opcode::PopR16(R16::DE),
         length: 1,
@@ -1061,7 +1061,7 @@ static LOOKUP: [OpcodeEntry; 256] = [
         opcode: Opcode::JpCCN16(CC::NC),
         length: 3,
         cycles: 12,
-    }, // 0xD2 JP NC,nn
+    }, // 0xD2 JP NC,nn (taken: 16, not taken: 12)
     OpcodeEntry {
         opcode: Opcode::Undefined,
         length: 1,
@@ -1071,7 +1071,7 @@ static LOOKUP: [OpcodeEntry; 256] = [
         opcode: Opcode::CallCCN16(CC::NC),
         length: 3,
         cycles: 12,
-    }, // 0xD4 CALL NC,nn
+    }, // 0xD4 CALL NC,nn (taken: 24, not taken: 12)
     OpcodeEntry {
         opcode: Opcode::PushR16(R16::DE),
         length: 1,
@@ -1091,7 +1091,7 @@ static LOOKUP: [OpcodeEntry; 256] = [
         opcode: Opcode::RetCC(CC::C),
         length: 1,
         cycles: 8,
-    }, // 0xD8 RET C
+    }, // 0xD8 RET C (taken: 20, not taken: 8)
     OpcodeEntry {
         opcode: Opcode::RetI,
         length: 1,
@@ -1101,7 +1101,7 @@ static LOOKUP: [OpcodeEntry; 256] = [
         opcode: Opcode::JpCCN16(CC::C),
         length: 3,
         cycles: 12,
-    }, // 0xDA JP C,nn
+    }, // 0xDA JP C,nn (taken: 16, not taken: 12)
     OpcodeEntry {
         opcode: Opcode::Undefined,
         length: 1,
@@ -1111,7 +1111,7 @@ static LOOKUP: [OpcodeEntry; 256] = [
         opcode: Opcode::CallCCN16(CC::C),
         length: 3,
         cycles: 12,
-    }, // 0xDC CALL C,nn
+    }, // 0xDC CALL C,nn (taken: 24, not taken: 12)
     OpcodeEntry {
         opcode: Opcode::Undefined,
         length: 1,
@@ -1171,7 +1171,7 @@ static LOOKUP: [OpcodeEntry; 256] = [
     OpcodeEntry {
         opcode: Opcode::AddSPe8,
         length: 2,
-        cycles: 8,
+        cycles: 16,
     }, // 0xE8 ADD SP, e
     OpcodeEntry {
         opcode: Opcode::JpHL,
@@ -1647,7 +1647,7 @@ static CB_LOOKUP: [OpcodeEntry; 256] = [
     OpcodeEntry {
         opcode: Opcode::Bit(0, R8::HLIndirect),
         length: 2,
-        cycles: 16,
+        cycles: 12,
     }, // CB46
     OpcodeEntry {
         opcode: Opcode::Bit(0, R8::A),
@@ -1688,7 +1688,7 @@ static CB_LOOKUP: [OpcodeEntry; 256] = [
     OpcodeEntry {
         opcode: Opcode::Bit(1, R8::HLIndirect),
         length: 2,
-        cycles: 16,
+        cycles: 12,
     }, // CB4E
     OpcodeEntry {
         opcode: Opcode::Bit(1, R8::A),
@@ -1729,7 +1729,7 @@ static CB_LOOKUP: [OpcodeEntry; 256] = [
     OpcodeEntry {
         opcode: Opcode::Bit(2, R8::HLIndirect),
         length: 2,
-        cycles: 16,
+        cycles: 12,
     }, // CB56
     OpcodeEntry {
         opcode: Opcode::Bit(2, R8::A),
@@ -1770,7 +1770,7 @@ static CB_LOOKUP: [OpcodeEntry; 256] = [
     OpcodeEntry {
         opcode: Opcode::Bit(3, R8::HLIndirect),
         length: 2,
-        cycles: 16,
+        cycles: 12,
     }, // CB5E
     OpcodeEntry {
         opcode: Opcode::Bit(3, R8::A),
@@ -1811,7 +1811,7 @@ static CB_LOOKUP: [OpcodeEntry; 256] = [
     OpcodeEntry {
         opcode: Opcode::Bit(4, R8::HLIndirect),
         length: 2,
-        cycles: 16,
+        cycles: 12,
     }, // CB66
     OpcodeEntry {
         opcode: Opcode::Bit(4, R8::A),
@@ -1851,7 +1851,7 @@ static CB_LOOKUP: [OpcodeEntry; 256] = [
     OpcodeEntry {
         opcode: Opcode::Bit(5, R8::HLIndirect),
         length: 2,
-        cycles: 16,
+        cycles: 12,
     }, // CB6E
     OpcodeEntry {
         opcode: Opcode::Bit(5, R8::A),
@@ -1891,7 +1891,7 @@ static CB_LOOKUP: [OpcodeEntry; 256] = [
     OpcodeEntry {
         opcode: Opcode::Bit(6, R8::HLIndirect),
         length: 2,
-        cycles: 16,
+        cycles: 12,
     }, // CB76
     OpcodeEntry {
         opcode: Opcode::Bit(6, R8::A),
@@ -1931,7 +1931,7 @@ static CB_LOOKUP: [OpcodeEntry; 256] = [
     OpcodeEntry {
         opcode: Opcode::Bit(7, R8::HLIndirect),
         length: 2,
-        cycles: 16,
+        cycles: 12,
     }, // CB7E
     OpcodeEntry {
         opcode: Opcode::Bit(7, R8::A),
@@ -2586,6 +2586,7 @@ static CB_LOOKUP: [OpcodeEntry; 256] = [
     }, // CBFF
 ];
 
+#[derive(Debug)]
 pub enum R8 {
     A,
     B,
@@ -2599,6 +2600,7 @@ pub enum R8 {
 
 #[allow(clippy::enum_variant_names)]
 #[allow(dead_code)]
+#[derive(Debug)]
 pub enum R16 {
     AF,
     BC,
@@ -2608,6 +2610,7 @@ pub enum R16 {
     PC,
 }
 
+#[derive(Debug)]
 pub enum CC {
     NZ,
     Z,
@@ -2617,6 +2620,7 @@ pub enum CC {
 
 #[allow(clippy::enum_variant_names)]
 #[allow(dead_code)]
+#[derive(Debug)]
 pub enum Opcode {
     // Load
     LdR8R8(R8, R8), // load value in right reg to left reg
@@ -2721,7 +2725,27 @@ pub enum Opcode {
 pub struct OpcodeEntry {
     pub opcode: Opcode,
     pub length: u8,
-    pub cycles: u8,
+    pub cycles: u8, // for conditional branches: cycles when the branch is not taken
+}
+
+// An instruction decoded ahead of time together with its operand bytes
+#[derive(Clone, Copy)]
+pub struct DecodedInstruction {
+    pub entry: &'static OpcodeEntry,
+    pub operands: [u8; 2],
+}
+
+impl OpcodeEntry {
+    // cycles spent when the instruction transfers control (jump, call, return)
+    pub fn cycles_taken(&self) -> u8 {
+        match self.opcode {
+            Opcode::JrCCE8(_) => 12,
+            Opcode::JpCCN16(_) => 16,
+            Opcode::CallCCN16(_) => 24,
+            Opcode::RetCC(_) => 20,
+            _ => self.cycles,
+        }
+    }
 }
 
 pub fn decode(byte: u8) -> &'static OpcodeEntry {
@@ -2794,6 +2818,25 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn conditional_branches_taken_cycles() {
+        // JR NZ,e8
+        assert_eq!(decode(0x20).cycles, 8);
+        assert_eq!(decode(0x20).cycles_taken(), 12);
+        // JP Z,nn
+        assert_eq!(decode(0xCA).cycles, 12);
+        assert_eq!(decode(0xCA).cycles_taken(), 16);
+        // CALL NC,nn
+        assert_eq!(decode(0xD4).cycles, 12);
+        assert_eq!(decode(0xD4).cycles_taken(), 24);
+        // RET C
+        assert_eq!(decode(0xD8).cycles, 8);
+        assert_eq!(decode(0xD8).cycles_taken(), 20);
+        // unconditional entries are the same either way
+        assert_eq!(decode(0xC3).cycles_taken(), 16);
+        assert_eq!(decode(0xCD).cycles_taken(), 24);
+    }
+
     #[test]
     fn decode_loads_and_halt() {
         // LD (HL),A+ related checks
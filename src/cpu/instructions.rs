@@ -1,15 +1,41 @@
 use crate::cpu::Cpu;
+use crate::cpu::bus::Bus;
+use crate::cpu::call_stack::{Frame, FrameKind};
 use crate::cpu::decoder::decode_cb;
 use crate::cpu::decoder::{CC, Opcode, OpcodeEntry, R8, R16};
 use crate::cpu::registers::Flags;
-use crate::mmu::{HIGH_RAM, Mmu};
+use crate::debug::Location;
+use crate::mmu::HIGH_RAM;
 
 impl Cpu {
     pub fn increment_pc(&mut self, v: u16) {
-        self.registers.pc += v;
+        self.registers.pc = self.registers.pc.wrapping_add(v);
     }
 
-    fn read_r8(&self, r: &R8, mmu: &mut Mmu) -> u8 {
+    // immediate operands follow the opcode byte, cached instructions carry
+    // them along and only spend the cycles
+    fn read_n8<B: Bus>(&self, bus: &mut B) -> u8 {
+        match self.operands {
+            Some([lo, _]) => {
+                bus.cpu_idle();
+                lo
+            }
+            None => bus.cpu_read_8(self.registers.pc.wrapping_add(1)),
+        }
+    }
+
+    fn read_n16<B: Bus>(&self, bus: &mut B) -> u16 {
+        match self.operands {
+            Some([lo, hi]) => {
+                bus.cpu_idle();
+                bus.cpu_idle();
+                (hi as u16) << 8 | lo as u16
+            }
+            None => bus.cpu_read_16(self.registers.pc.wrapping_add(1)),
+        }
+    }
+
+    fn read_r8<B: Bus>(&self, r: &R8, bus: &mut B) -> u8 {
         match r {
             R8::A => self.registers.a,
             R8::B => self.registers.b,
@@ -20,12 +46,12 @@ impl Cpu {
             R8::L => self.registers.l,
             R8::HLIndirect => {
                 let addr = self.registers.hl();
-                mmu.read_8(addr)
+                bus.cpu_read_8(addr)
             }
         }
     }
 
-    fn write_r8(&mut self, r: &R8, value: u8, mmu: &mut Mmu) {
+    fn write_r8<B: Bus>(&mut self, r: &R8, value: u8, bus: &mut B) {
         match r {
             R8::A => self.registers.a = value,
             R8::B => self.registers.b = value,
@@ -36,7 +62,7 @@ impl Cpu {
             R8::L => self.registers.l = value,
             R8::HLIndirect => {
                 let addr = self.registers.hl();
-                mmu.write_8(addr, value);
+                bus.cpu_write_8(addr, value);
             }
         }
     }
@@ -63,28 +89,50 @@ impl Cpu {
         }
     }
 
-    fn push_u8(&mut self, mmu: &mut Mmu, value: u8) {
-        self.registers.sp -= 1;
-        mmu.write_8(self.registers.sp, value);
+    fn push_u8<B: Bus>(&mut self, bus: &mut B, value: u8) {
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        bus.cpu_write_8(self.registers.sp, value);
     }
 
-    fn pop_u8(&mut self, mmu: &mut Mmu) -> u8 {
-        let value = mmu.read_8(self.registers.sp);
-        self.registers.sp += 1;
+    fn pop_u8<B: Bus>(&mut self, bus: &mut B) -> u8 {
+        let value = bus.cpu_read_8(self.registers.sp);
+        self.registers.sp = self.registers.sp.wrapping_add(1);
         value
     }
 
-    pub fn push_u16(&mut self, mmu: &mut Mmu, value: u16) {
-        self.push_u8(mmu, (value >> 8) as u8);
-        self.push_u8(mmu, value as u8);
+    pub fn push_u16<B: Bus>(&mut self, bus: &mut B, value: u16) {
+        // every 16-bit push is preceded by an internal cycle (PUSH, CALL, RST, interrupts)
+        bus.cpu_idle();
+        self.push_u8(bus, (value >> 8) as u8);
+        self.push_u8(bus, value as u8);
     }
 
-    fn pop_u16(&mut self, mmu: &mut Mmu) -> u16 {
-        let low = self.pop_u8(mmu) as u16;
-        let high = self.pop_u8(mmu) as u16;
+    fn pop_u16<B: Bus>(&mut self, bus: &mut B) -> u16 {
+        let low = self.pop_u8(bus) as u16;
+        let high = self.pop_u8(bus) as u16;
         (high << 8) | low
     }
 
+    // pushes the return address and jumps, recording the call in the shadow stack
+    pub fn call<B: Bus>(&mut self, bus: &mut B, kind: FrameKind, target: u16, return_addr: u16) {
+        self.push_u16(bus, return_addr);
+        self.call_stack.push(Frame {
+            kind,
+            function: Location::of(bus, target),
+            caller: self.registers.pc,
+            return_addr,
+            sp: self.registers.sp,
+        });
+        self.registers.pc = target;
+    }
+
+    fn ret<B: Bus>(&mut self, bus: &mut B) {
+        let sp = self.registers.sp;
+        let target = self.pop_u16(bus);
+        self.call_stack.ret(self.registers.pc, target, sp);
+        self.registers.pc = target;
+    }
+
     fn condition_met(&self, cc: &CC) -> bool {
         match cc {
             CC::NZ => !self.registers.get_flag(Flags::Z),
@@ -94,80 +142,76 @@ impl Cpu {
         }
     }
 
-    pub fn execute_instruction(
-        &mut self,
-        entry: &OpcodeEntry,
-        mmu: &mut Mmu,
-        increment: bool,
-    ) -> bool {
-        let mut increment = increment;
-        self.instruction_number += 1;
+    // executes a single instruction and returns the number of cycles it took
+    pub fn execute_instruction<B: Bus>(&mut self, entry: &OpcodeEntry, bus: &mut B) -> u32 {
+        let mut branched = false;
         match &entry.opcode {
             Opcode::LdR8R8(reg, vreg) => {
-                let value = self.read_r8(vreg, mmu);
-                self.write_r8(reg, value, mmu);
+                let value = self.read_r8(vreg, bus);
+                self.write_r8(reg, value, bus);
             }
             Opcode::LdR8N8(reg) => {
-                let value = mmu.read_8(self.registers.pc + 1);
-                self.write_r8(reg, value, mmu);
+                let value = self.read_n8(bus);
+                self.write_r8(reg, value, bus);
             }
             Opcode::LdR16N16(reg) => {
-                let value = mmu.read_16(self.registers.pc + 1);
+                let value = self.read_n16(bus);
                 self.write_r16(reg, value);
             }
             Opcode::LdPtrR16A(reg) => {
                 let addr = self.read_r16(reg);
-                mmu.write_8(addr, self.registers.a);
+                bus.cpu_write_8(addr, self.registers.a);
             }
             Opcode::LdPtrN16A => {
-                let addr = mmu.read_16(self.registers.pc + 1);
-                mmu.write_8(addr, self.registers.a);
+                let addr = self.read_n16(bus);
+                bus.cpu_write_8(addr, self.registers.a);
             }
             Opcode::LdHPtrCA => {
                 let addr = HIGH_RAM | self.registers.c as u16;
-                mmu.write_8(addr, self.registers.a);
+                bus.cpu_write_8(addr, self.registers.a);
             }
             Opcode::LdAPtrR16(reg) => {
                 let addr = self.read_r16(reg);
-                self.registers.a = mmu.read_8(addr)
+                self.registers.a = bus.cpu_read_8(addr)
             }
             Opcode::LdAPtrN16 => {
-                self.registers.a = mmu.read_8(mmu.read_16(self.registers.pc + 1));
+                let addr = self.read_n16(bus);
+                self.registers.a = bus.cpu_read_8(addr);
             }
             Opcode::LdHAPtrC => {
                 let addr = HIGH_RAM | self.registers.c as u16;
-                self.registers.a = mmu.read_8(addr);
+                self.registers.a = bus.cpu_read_8(addr);
             }
             Opcode::LDHAPtrN8 => {
-                let addr = HIGH_RAM | mmu.read_8(self.registers.pc + 1) as u16;
-                self.registers.a = mmu.read_8(addr);
+                let addr = HIGH_RAM | self.read_n8(bus) as u16;
+                self.registers.a = bus.cpu_read_8(addr);
             }
             Opcode::LDHPtrN8A => {
-                let addr = HIGH_RAM | mmu.read_8(self.registers.pc + 1) as u16;
-                mmu.write_8(addr, self.registers.a);
+                let addr = HIGH_RAM | self.read_n8(bus) as u16;
+                bus.cpu_write_8(addr, self.registers.a);
             }
             Opcode::LdPtrHLIncA => {
                 let addr = self.registers.hl();
-                mmu.write_8(addr, self.registers.a);
-                self.registers.set_hl(self.registers.hl() + 1);
+                bus.cpu_write_8(addr, self.registers.a);
+                self.registers.set_hl(self.registers.hl().wrapping_add(1));
             }
             Opcode::LdPtrHLDecA => {
                 let addr = self.registers.hl();
-                mmu.write_8(addr, self.registers.a);
-                self.registers.set_hl(self.registers.hl() - 1);
+                bus.cpu_write_8(addr, self.registers.a);
+                self.registers.set_hl(self.registers.hl().wrapping_sub(1));
             }
             Opcode::LdAPtrHLDec => {
                 let addr = self.registers.hl();
-                self.registers.a = mmu.read_8(addr);
-                self.registers.set_hl(self.registers.hl() - 1);
+                self.registers.a = bus.cpu_read_8(addr);
+                self.registers.set_hl(self.registers.hl().wrapping_sub(1));
             }
             Opcode::LdAPtrHLInc => {
                 let addr = self.registers.hl();
-                self.registers.a = mmu.read_8(addr);
-                self.registers.set_hl(self.registers.hl() + 1);
+                self.registers.a = bus.cpu_read_8(addr);
+                self.registers.set_hl(self.registers.hl().wrapping_add(1));
             }
             Opcode::AdcAR8(reg) => {
-                let value = self.read_r8(reg, mmu);
+                let value = self.read_r8(reg, bus);
                 let result = self
                     .registers
                     .a
@@ -186,7 +230,7 @@ impl Cpu {
                 self.registers.set_flags(z, false, h, c);
             }
             Opcode::AdcAN8 => {
-                let value = mmu.read_8(self.registers.pc + 1);
+                let value = self.read_n8(bus);
                 let result = self
                     .registers
                     .a
@@ -205,7 +249,7 @@ impl Cpu {
                 self.registers.set_flags(z, false, h, c);
             }
             Opcode::AddAR8(reg) => {
-                let value = self.read_r8(reg, mmu);
+                let value = self.read_r8(reg, bus);
                 let result = self.registers.a.wrapping_add(value);
                 let z = result == 0;
                 let h = ((self.registers.a & 0x0F) + (value & 0x0F)) > 0x0F;
@@ -214,7 +258,7 @@ impl Cpu {
                 self.registers.set_flags(z, false, h, c);
             }
             Opcode::AddAN8 => {
-                let value = mmu.read_8(self.registers.pc + 1);
+                let value = self.read_n8(bus);
                 let result = self.registers.a.wrapping_add(value);
                 let z = result == 0;
                 let h = ((self.registers.a & 0x0F) + (value & 0x0F)) > 0x0F;
@@ -223,7 +267,7 @@ impl Cpu {
                 self.registers.set_flags(z, false, h, c);
             }
             Opcode::CpAR8(reg) => {
-                let value = self.read_r8(reg, mmu);
+                let value = self.read_r8(reg, bus);
                 let result = self.registers.a.wrapping_sub(value);
                 let z = result == 0;
                 let h = (value & 0x0F) > (self.registers.a & 0x0F);
@@ -231,7 +275,7 @@ impl Cpu {
                 self.registers.set_flags(z, true, h, c);
             }
             Opcode::CpAN8 => {
-                let value = mmu.read_8(self.registers.pc + 1);
+                let value = self.read_n8(bus);
                 let result = self.registers.a.wrapping_sub(value);
                 let z = result == 0;
                 let h = (value & 0x0F) > (self.registers.a & 0x0F);
@@ -239,25 +283,25 @@ impl Cpu {
                 self.registers.set_flags(z, true, h, c);
             }
             Opcode::DecR8(reg) => {
-                let old = self.read_r8(reg, mmu);
+                let old = self.read_r8(reg, bus);
                 let result = old.wrapping_sub(1);
-                self.write_r8(reg, result, mmu);
+                self.write_r8(reg, result, bus);
 
                 self.registers.set_flag(Flags::Z, result == 0);
                 self.registers.set_flag(Flags::N, true);
                 self.registers.set_flag(Flags::H, (old & 0x0F) == 0);
             }
             Opcode::IncR8(reg) => {
-                let old = self.read_r8(reg, mmu);
+                let old = self.read_r8(reg, bus);
                 let result = old.wrapping_add(1);
-                self.write_r8(reg, result, mmu);
+                self.write_r8(reg, result, bus);
 
                 self.registers.set_flag(Flags::Z, result == 0);
                 self.registers.set_flag(Flags::N, false);
                 self.registers.set_flag(Flags::H, (old & 0x0F) == 0x0F);
             }
             Opcode::SbcAR8(reg) => {
-                let value = self.read_r8(reg, mmu);
+                let value = self.read_r8(reg, bus);
                 let result = self
                     .registers
                     .a
@@ -272,7 +316,7 @@ impl Cpu {
                 self.registers.set_flags(z, true, h, c);
             }
             Opcode::SbcAN8 => {
-                let value = mmu.read_8(self.registers.pc + 1);
+                let value = self.read_n8(bus);
                 let result = self
                     .registers
                     .a
@@ -287,7 +331,7 @@ impl Cpu {
                 self.registers.set_flags(z, true, h, c);
             }
             Opcode::SubAR8(reg) => {
-                let value = self.read_r8(reg, mmu);
+                let value = self.read_r8(reg, bus);
                 let result = self.registers.a.wrapping_sub(value);
                 let z = result == 0;
                 let h = self.registers.a & 0x0F < (value & 0x0F);
@@ -296,7 +340,7 @@ impl Cpu {
                 self.registers.set_flags(z, true, h, c);
             }
             Opcode::SubAN8 => {
-                let value = mmu.read_8(self.registers.pc + 1);
+                let value = self.read_n8(bus);
                 let result = self.registers.a.wrapping_sub(value);
                 let z = result == 0;
                 let h = self.registers.a & 0x0F < (value & 0x0F);
@@ -323,12 +367,12 @@ impl Cpu {
                 self.write_r16(reg, value.wrapping_add(1));
             }
             Opcode::AndAR8(reg) => {
-                self.registers.a &= self.read_r8(reg, mmu);
+                self.registers.a &= self.read_r8(reg, bus);
                 self.registers
                     .set_flags(self.registers.a == 0, false, true, false);
             }
             Opcode::AndAN8 => {
-                self.registers.a &= mmu.read_8(self.registers.pc + 1);
+                self.registers.a &= self.read_n8(bus);
                 self.registers
                     .set_flags(self.registers.a == 0, false, true, false);
             }
@@ -338,49 +382,49 @@ impl Cpu {
                 self.registers.set_flag(Flags::H, true);
             }
             Opcode::OrAR8(reg) => {
-                self.registers.a |= self.read_r8(reg, mmu);
+                self.registers.a |= self.read_r8(reg, bus);
                 self.registers
                     .set_flags(self.registers.a == 0, false, false, false);
             }
             Opcode::OrAN8 => {
-                self.registers.a |= mmu.read_8(self.registers.pc + 1);
+                self.registers.a |= self.read_n8(bus);
                 self.registers
                     .set_flags(self.registers.a == 0, false, false, false);
             }
             Opcode::XorAR8(reg) => {
-                self.registers.a ^= self.read_r8(reg, mmu);
+                self.registers.a ^= self.read_r8(reg, bus);
                 self.registers
                     .set_flags(self.registers.a == 0, false, false, false);
             }
             Opcode::XorAN8 => {
-                self.registers.a ^= mmu.read_8(self.registers.pc + 1);
+                self.registers.a ^= self.read_n8(bus);
                 self.registers
                     .set_flags(self.registers.a == 0, false, false, false);
             }
             Opcode::Bit(n, reg) => {
                 self.registers
-                    .set_flag(Flags::Z, ((self.read_r8(reg, mmu) >> n) & 1) == 0);
+                    .set_flag(Flags::Z, ((self.read_r8(reg, bus) >> n) & 1) == 0);
                 self.registers.set_flag(Flags::N, false);
                 self.registers.set_flag(Flags::H, true);
             }
             Opcode::Set(n, reg) => {
-                let value = self.read_r8(reg, mmu);
+                let value = self.read_r8(reg, bus);
                 let result = value | (1 << n);
-                self.write_r8(reg, result, mmu);
+                self.write_r8(reg, result, bus);
             }
             Opcode::Res(n, reg) => {
-                let value = self.read_r8(reg, mmu);
+                let value = self.read_r8(reg, bus);
                 let result = value & !(1 << n);
-                self.write_r8(reg, result, mmu);
+                self.write_r8(reg, result, bus);
             }
             Opcode::RlR8(reg) => {
-                let val = self.read_r8(reg, mmu);
+                let val = self.read_r8(reg, bus);
                 let old_carry = self.registers.get_flag(Flags::C) as u8;
 
                 self.registers.set_flag(Flags::C, (val & 0x80) != 0);
 
                 let result = (val << 1) | old_carry;
-                self.write_r8(reg, result, mmu);
+                self.write_r8(reg, result, bus);
                 self.registers.set_flag(Flags::Z, result == 0);
                 self.registers.set_flag(Flags::N, false);
                 self.registers.set_flag(Flags::H, false);
@@ -396,12 +440,12 @@ impl Cpu {
                 self.registers.set_flag(Flags::H, false);
             }
             Opcode::RlcR8(reg) => {
-                let val = self.read_r8(reg, mmu);
+                let val = self.read_r8(reg, bus);
 
                 self.registers.set_flag(Flags::C, (val & 0x80) != 0);
 
                 let result = val.rotate_left(1);
-                self.write_r8(reg, result, mmu);
+                self.write_r8(reg, result, bus);
                 self.registers.set_flag(Flags::Z, result == 0);
                 self.registers.set_flag(Flags::N, false);
                 self.registers.set_flag(Flags::H, false);
@@ -416,13 +460,13 @@ impl Cpu {
                 self.registers.set_flag(Flags::H, false);
             }
             Opcode::RrR8(reg) => {
-                let val = self.read_r8(reg, mmu);
+                let val = self.read_r8(reg, bus);
                 let old_carry = self.registers.get_flag(Flags::C) as u8;
 
                 self.registers.set_flag(Flags::C, (val & 0x01) != 0);
 
                 let result = (val >> 1) | (old_carry << 7);
-                self.write_r8(reg, result, mmu);
+                self.write_r8(reg, result, bus);
                 self.registers.set_flag(Flags::Z, result == 0);
                 self.registers.set_flag(Flags::N, false);
                 self.registers.set_flag(Flags::H, false);
@@ -438,12 +482,12 @@ impl Cpu {
                 self.registers.set_flag(Flags::H, false);
             }
             Opcode::RrcR8(reg) => {
-                let val = self.read_r8(reg, mmu);
+                let val = self.read_r8(reg, bus);
 
                 self.registers.set_flag(Flags::C, (val & 0x01) != 0);
 
                 let result = val.rotate_right(1);
-                self.write_r8(reg, result, mmu);
+                self.write_r8(reg, result, bus);
                 self.registers.set_flag(Flags::Z, result == 0);
                 self.registers.set_flag(Flags::N, false);
                 self.registers.set_flag(Flags::H, false);
@@ -458,110 +502,107 @@ impl Cpu {
                 self.registers.set_flag(Flags::H, false);
             }
             Opcode::SlaR8(reg) => {
-                let val = self.read_r8(reg, mmu);
+                let val = self.read_r8(reg, bus);
                 self.registers.set_flag(Flags::C, (val & 0x80) != 0);
 
                 let result = val << 1;
-                self.write_r8(reg, result, mmu);
+                self.write_r8(reg, result, bus);
                 self.registers.set_flag(Flags::Z, result == 0);
                 self.registers.set_flag(Flags::N, false);
                 self.registers.set_flag(Flags::H, false);
             }
             Opcode::SraR8(reg) => {
-                let val = self.read_r8(reg, mmu);
+                let val = self.read_r8(reg, bus);
                 self.registers.set_flag(Flags::C, (val & 0x01) != 0);
 
                 let result = (val >> 1) | (val & 0x80); // preserve bit 7
-                self.write_r8(reg, result, mmu);
+                self.write_r8(reg, result, bus);
                 self.registers.set_flag(Flags::Z, result == 0);
                 self.registers.set_flag(Flags::N, false);
                 self.registers.set_flag(Flags::H, false);
             }
             Opcode::SrlR8(reg) => {
-                let val = self.read_r8(reg, mmu);
+                let val = self.read_r8(reg, bus);
                 self.registers.set_flag(Flags::C, (val & 0x01) != 0);
 
                 let result = val >> 1; // preserve bit 7
-                self.write_r8(reg, result, mmu);
+                self.write_r8(reg, result, bus);
                 self.registers.set_flag(Flags::Z, result == 0);
                 self.registers.set_flag(Flags::N, false);
                 self.registers.set_flag(Flags::H, false);
             }
             Opcode::SwapR8(reg) => {
-                let val = self.read_r8(reg, mmu);
+                let val = self.read_r8(reg, bus);
                 let result = val.rotate_left(4);
-                self.write_r8(reg, result, mmu);
+                self.write_r8(reg, result, bus);
                 self.registers.set_flags(result == 0, false, false, false);
             }
             Opcode::CallN16 => {
-                let target = mmu.read_16(self.registers.pc + 1);
-                let ret = self.registers.pc + 3;
-                // pushes instruction after to the stack
-                self.push_u16(mmu, ret);
-                // jp n16
-                self.registers.pc = target;
-                increment = false;
+                let target = self.read_n16(bus);
+                // pushes instruction after to the stack and jumps
+                let ret = self.registers.pc.wrapping_add(3);
+                self.call(bus, FrameKind::Call, target, ret);
+                branched = true;
             }
             Opcode::CallCCN16(cc) => {
+                // the operand is read whether or not the call is taken
+                let target = self.read_n16(bus);
                 if self.condition_met(cc) {
-                    //TODO: cycles
-                    let target = mmu.read_16(self.registers.pc + 1);
-                    let ret = self.registers.pc + 3;
-
-                    self.push_u16(mmu, ret);
-                    self.registers.pc = target;
-                    increment = false;
+                    let ret = self.registers.pc.wrapping_add(3);
+                    self.call(bus, FrameKind::Call, target, ret);
+                    branched = true;
                 }
             }
             Opcode::JpHL => {
-                increment = false;
+                branched = true;
                 self.registers.pc = self.registers.hl();
             }
             Opcode::JpN16 => {
-                self.registers.pc = mmu.read_16(self.registers.pc.wrapping_add(1));
-                increment = false;
+                self.registers.pc = self.read_n16(bus);
+                branched = true;
             }
             Opcode::JpCCN16(cc) => {
+                let target = self.read_n16(bus);
                 if self.condition_met(cc) {
-                    //TODO: CYCLES
-                    self.registers.pc = mmu.read_16(self.registers.pc.wrapping_add(1));
-                    increment = false;
+                    self.registers.pc = target;
+                    branched = true;
                 }
             }
             Opcode::JrE8 => {
-                let offset = mmu.read_8(self.registers.pc + 1) as i8;
+                let offset = self.read_n8(bus) as i8;
                 let pc = self.registers.pc.wrapping_add(2);
-                self.registers.pc = ((pc as i32) + (offset as i32)) as u16;
-                increment = false;
+                self.registers.pc = pc.wrapping_add_signed(offset as i16);
+                branched = true;
             }
             Opcode::JrCCE8(cc) => {
+                let offset = self.read_n8(bus) as i8;
                 if self.condition_met(cc) {
-                    let offset = mmu.read_8(self.registers.pc + 1) as i8;
                     let pc = self.registers.pc.wrapping_add(2);
-                    self.registers.pc = ((pc as i32) + (offset as i32)) as u16;
-                    increment = false;
+                    self.registers.pc = pc.wrapping_add_signed(offset as i16);
+                    branched = true;
                 }
             }
             Opcode::Ret => {
-                self.registers.pc = self.pop_u16(mmu);
-                increment = false;
+                self.ret(bus);
+                branched = true;
             }
             Opcode::RetCC(cc) => {
+                // the condition check takes an extra cycle before the stack is read
+                bus.cpu_idle();
                 if self.condition_met(cc) {
-                    self.registers.pc = self.pop_u16(mmu);
-                    increment = false;
+                    self.ret(bus);
+                    branched = true;
                 }
             }
             Opcode::RetI => {
                 self.interrupts.set_ime();
-                self.registers.pc = self.pop_u16(mmu);
-                increment = false;
+                self.ret(bus);
+                branched = true;
             }
             Opcode::Rst(vec) => {
-                let ret = self.registers.pc + entry.length as u16;
-                self.push_u16(mmu, ret);
-                self.registers.pc = *vec as u16;
-                increment = false;
+                let ret = self.registers.pc.wrapping_add(entry.length as u16);
+                self.call(bus, FrameKind::Rst, *vec as u16, ret);
+                branched = true;
             }
             Opcode::Scf => {
                 self.registers.set_flag(Flags::H, false);
@@ -585,10 +626,10 @@ impl Cpu {
                 self.registers.set_hl(hl.wrapping_add(value));
             }
             Opcode::AddSPe8 => {
-                let e = mmu.read_8(self.registers.pc + 1) as i8;
+                let e = self.read_n8(bus) as i8;
                 let sp = self.registers.sp;
 
-                let result = (sp as i32 + e as i32) as u16;
+                let result = sp.wrapping_add_signed(e as i16);
 
                 self.registers.set_flag(Flags::Z, false);
                 self.registers.set_flag(Flags::N, false);
@@ -600,7 +641,7 @@ impl Cpu {
                 self.registers.sp = result;
             }
             Opcode::LdHLSPe8 => {
-                let offset = mmu.read_8(self.registers.pc + 1) as i8;
+                let offset = self.read_n8(bus) as i8;
                 let sp = self.registers.sp;
 
                 let unsigned = offset as u16;
@@ -612,8 +653,7 @@ impl Cpu {
                 self.registers
                     .set_flag(Flags::C, ((sp & 0x00FF) + (unsigned & 0x00FF)) > 0x00FF);
 
-                let result = (sp as i32) + (offset as i32);
-                self.registers.set_hl(result as u16);
+                self.registers.set_hl(sp.wrapping_add_signed(offset as i16));
             }
             Opcode::DecSP => {
                 self.registers.sp = self.registers.sp.wrapping_sub(1);
@@ -622,30 +662,30 @@ impl Cpu {
                 self.registers.sp = self.registers.sp.wrapping_add(1);
             }
             Opcode::LdSPN16 => {
-                self.registers.sp = mmu.read_16(self.registers.pc + 1);
+                self.registers.sp = self.read_n16(bus);
             }
             Opcode::LdPtrN16SP => {
-                let addr = mmu.read_16(self.registers.pc + 1);
-                mmu.write_8(addr, (self.registers.sp & 0x00FF) as u8);
-                mmu.write_8(addr + 1, (self.registers.sp >> 8) as u8);
+                let addr = self.read_n16(bus);
+                bus.cpu_write_8(addr, (self.registers.sp & 0x00FF) as u8);
+                bus.cpu_write_8(addr.wrapping_add(1), (self.registers.sp >> 8) as u8);
             }
             Opcode::LdSPHL => {
                 self.registers.sp = self.registers.hl();
             }
             Opcode::PopAF => {
-                self.registers.f = self.pop_u8(mmu) & 0xF0; // mask low 4 bits
-                self.registers.a = self.pop_u8(mmu);
+                self.registers.f = self.pop_u8(bus) & 0xF0; // mask low 4 bits
+                self.registers.a = self.pop_u8(bus);
             }
             Opcode::PopR16(reg) => {
-                let value = self.pop_u16(mmu);
+                let value = self.pop_u16(bus);
                 self.write_r16(reg, value);
             }
             Opcode::PushAF => {
-                self.push_u16(mmu, self.registers.af());
+                self.push_u16(bus, self.registers.af());
             }
             Opcode::PushR16(reg) => {
                 let value = self.read_r16(reg);
-                self.push_u16(mmu, value);
+                self.push_u16(bus, value);
             }
             Opcode::Di => {
                 self.interrupts.reset_ime();
@@ -654,7 +694,13 @@ impl Cpu {
                 self.interrupts.ime_scheduled = true;
             }
             Opcode::Halt => {
-                self.interrupts.halted = true;
+                if !self.interrupts.ime && bus.pending_interrupts() != 0 {
+                    // HALT bug: the CPU doesn't halt and fails to increment PC
+                    // after the next opcode fetch
+                    self.interrupts.halt_bug = true;
+                } else {
+                    self.interrupts.halted = true;
+                }
             }
             Opcode::Daa => {
                 let mut a = self.registers.a;
@@ -689,19 +735,29 @@ impl Cpu {
                 // Nothing
             }
             Opcode::Stop => {
-                mmu.timer.reset_div();
+                // CGB: STOP with KEY1 bit 0 set switches CPU speed instead of stopping
+                if !bus.stop() {
+                    self.interrupts.stopped = true;
+                }
             }
             Opcode::Undefined => {
-                // Undefined
+                // illegal opcodes hang the CPU for good
+                let opcode_byte = bus.peek(self.registers.pc);
+                self.locked = Some((self.registers.pc, opcode_byte));
+                branched = true;
             }
             Opcode::Prefix => {
-                increment = false;
-                let opcode_byte = mmu.read_8(self.registers.pc.wrapping_add(1));
-                let entry = decode_cb(opcode_byte);
-                self.registers.pc += 2;
-                self.execute_instruction(entry, mmu, false);
+                // CB entries are 2 bytes long and carry the full cycle count
+                let opcode_byte = self.read_n8(bus);
+                return self.execute_instruction(decode_cb(opcode_byte), bus);
             }
         }
-        increment
+
+        if branched {
+            entry.cycles_taken() as u32
+        } else {
+            self.increment_pc(entry.length as u16);
+            entry.cycles as u32
+        }
     }
 }
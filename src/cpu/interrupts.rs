@@ -1,7 +1,13 @@
+use std::io::Error;
+
+use crate::state::{SaveState, StateReader, StateWriter};
+
 pub struct Interrupts {
     pub ime: bool,
     pub ime_scheduled: bool,
     pub halted: bool,
+    pub halt_bug: bool,
+    pub stopped: bool,
 }
 
 impl Interrupts {
@@ -10,6 +16,8 @@ impl Interrupts {
             ime: false,
             ime_scheduled: false,
             halted: false,
+            halt_bug: false,
+            stopped: false,
         }
     }
 
@@ -20,5 +28,31 @@ impl Interrupts {
 
     pub fn reset_ime(&mut self) {
         self.ime = false;
+        self.ime_scheduled = false;
+    }
+}
+
+impl Default for Interrupts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SaveState for Interrupts {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.ime);
+        state.bool(self.ime_scheduled);
+        state.bool(self.halted);
+        state.bool(self.halt_bug);
+        state.bool(self.stopped);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Error> {
+        self.ime = state.bool()?;
+        self.ime_scheduled = state.bool()?;
+        self.halted = state.bool()?;
+        self.halt_bug = state.bool()?;
+        self.stopped = state.bool()?;
+        Ok(())
     }
 }
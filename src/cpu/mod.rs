@@ -1,18 +1,21 @@
+pub mod bus;
+pub mod call_stack;
 pub mod decoder;
 pub mod instructions;
 pub mod interrupts;
 pub mod registers;
 
-use decoder::decode;
+use call_stack::{CallStack, FrameKind};
+use decoder::{Opcode, R8, decode, decode_cb};
 use registers::Registers;
 use std::io::Write;
 use std::{fs::File, io::Error};
 
 use crate::{
-    cpu::{decoder::OpcodeEntry, interrupts::Interrupts},
-    debug::{Debug, disasm::disassemble},
+    cpu::{bus::Bus, decoder::OpcodeEntry, interrupts::Interrupts},
+    debug::{Debug, Location, disasm::disassemble},
     interrupt_controller::Interrupt,
-    mmu::Mmu,
+    state::{SaveState, StateReader, StateWriter},
 };
 
 pub struct Cpu {
@@ -20,6 +23,10 @@ pub struct Cpu {
     pub interrupts: Interrupts,
     pub history: Vec<String>,
     pub instruction_number: u128,
+    pub locked: Option<(u16, u8)>, // (pc, opcode) of the illegal opcode that hung the CPU
+    pub call_stack: CallStack,
+    // operand bytes of the current instruction when it came from the code cache
+    operands: Option<[u8; 2]>,
 }
 
 impl Cpu {
@@ -29,46 +36,118 @@ impl Cpu {
             interrupts: Interrupts::new(),
             history: Vec::new(),
             instruction_number: 0,
+            locked: None,
+            call_stack: CallStack::new(),
+            operands: None,
         }
     }
 
-    pub fn step(&mut self, mmu: &mut Mmu, debug: &Debug) -> u32 {
-        if self.interrupts.ime_scheduled {
-            self.interrupts.set_ime();
+    pub fn step<B: Bus>(&mut self, bus: &mut B, debug: &mut Debug) -> u32 {
+        // nothing, not even an interrupt, gets a locked CPU going again
+        if self.locked.is_some() {
+            return 4;
         }
 
-        if self.interrupts.halted {
-            if mmu.interrupts.pending_mask() != 0 {
-                self.interrupts.halted = false;
+        if self.interrupts.stopped {
+            // STOP mode lasts until one of the selected joypad lines goes low
+            if bus.joypad_lines() == 0x0F {
+                return 4;
             }
+            self.interrupts.stopped = false;
+        }
 
-            return 4;
+        if self.interrupts.halted {
+            if bus.pending_interrupts() == 0 {
+                return 4;
+            }
+            // any pending interrupt ends HALT, with IME=0 execution simply
+            // resumes after the HALT without servicing it
+            self.interrupts.halted = false;
         }
 
         if self.interrupts.ime
-            && let Some(irq) = mmu.interrupts.highest()
+            && let Some(irq) = bus.highest_interrupt()
         {
-            mmu.interrupts.ack(irq);
-            self.service_interrupt(irq, mmu);
+            bus.ack_interrupt(irq);
+            self.service_interrupt(irq, bus);
+            if let Some(profiler) = &mut debug.profiler {
+                let vector = Location::of(bus, self.registers.pc);
+                profiler.interrupt(vector, 20, &self.call_stack.frames);
+            }
             return 20;
         }
 
-        let opcode_byte = mmu.read_8(self.registers.pc);
-        let entry = decode(opcode_byte);
+        if let Some(trace) = &mut debug.trace {
+            trace.log(self, bus);
+        }
+
+        // EI takes effect only after the instruction that follows it
+        let enable_ime = self.interrupts.ime_scheduled;
+
+        // the HALT bug shifts the operands, take that path through memory
+        let cached = match self.interrupts.halt_bug {
+            true => None,
+            false => bus.cached_instruction(self.registers.pc),
+        };
+        let entry = match cached {
+            Some(cached) => {
+                // keeps the M-cycle of the opcode fetch
+                bus.cpu_idle();
+                self.operands = Some(cached.operands);
+                cached.entry
+            }
+            None => decode(bus.cpu_read_8(self.registers.pc)),
+        };
 
         if debug.log_cpu {
-            self.history.push(disassemble(&entry.opcode, mmu, self));
+            self.history.push(disassemble(&entry.opcode, bus, self));
+        }
+
+        if self.interrupts.halt_bug {
+            // HALT bug: PC fails to increment after the opcode fetch, so the
+            // opcode byte is read again as the first operand (or next opcode)
+            self.interrupts.halt_bug = false;
+            self.registers.pc = self.registers.pc.wrapping_sub(1);
+        }
+
+        if matches!(entry.opcode, Opcode::LdR8R8(R8::B, R8::B)) {
+            debug.trap();
+        }
+
+        let pc = self.registers.pc;
+        self.instruction_number += 1;
+        let cycles = self.execute_instruction(entry, bus);
+        self.operands = None;
+
+        if let Some(profiler) = &mut debug.profiler {
+            let opcode = match entry.opcode {
+                Opcode::Prefix => &decode_cb(bus.peek(pc.wrapping_add(1))).opcode,
+                _ => &entry.opcode,
+            };
+            let taken = self.registers.pc != pc.wrapping_add(entry.length as u16);
+            let next = Location::of(bus, self.registers.pc);
+            let frames = &self.call_stack.frames;
+            profiler.record(Location::of(bus, pc), opcode, cycles, taken, next, frames);
         }
 
-        if self.execute_instruction(entry, mmu, true) {
-            self.increment_pc(entry.length as u16);
+        if enable_ime && self.interrupts.ime_scheduled {
+            self.interrupts.set_ime();
         }
 
-        entry.cycles as u32
+        cycles
+    }
+
+    pub fn lockup_message(&self) -> Option<String> {
+        self.locked.map(|(pc, opcode)| {
+            format!(
+                "CPU locked at ${:04X} by illegal opcode ${:02X}",
+                pc, opcode
+            )
+        })
     }
 
-    pub fn get_current_opcode(&self, mmu: &Mmu) -> &OpcodeEntry {
-        let opcode_byte = mmu.read_8(self.registers.pc);
+    pub fn get_current_opcode<B: Bus>(&self, bus: &B) -> &OpcodeEntry {
+        let opcode_byte = bus.peek(self.registers.pc);
         decode(opcode_byte)
     }
 
@@ -82,13 +161,475 @@ impl Cpu {
         Ok(())
     }
 
-    fn service_interrupt(&mut self, irq: Interrupt, mmu: &mut Mmu) {
+    fn service_interrupt<B: Bus>(&mut self, irq: Interrupt, bus: &mut B) {
         self.interrupts.ime = false;
         self.interrupts.ime_scheduled = false;
         self.interrupts.halted = false;
 
-        let pc = self.registers.pc;
-        self.push_u16(mmu, pc);
-        self.registers.pc = irq.vector();
+        bus.cpu_idle();
+        let mut pc = self.registers.pc;
+        if self.interrupts.halt_bug {
+            // EI; HALT with a pending interrupt returns to the HALT itself
+            self.interrupts.halt_bug = false;
+            pc = pc.wrapping_sub(1);
+        }
+        // the interrupted instruction is reported as the caller
+        self.registers.pc = pc;
+        self.call(bus, FrameKind::Interrupt, irq.vector(), pc);
+    }
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SaveState for Cpu {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.registers.save_state(state);
+        self.interrupts.save_state(state);
+        state.bool(self.locked.is_some());
+        let (pc, opcode) = self.locked.unwrap_or_default();
+        state.u16(pc);
+        state.u8(opcode);
+        state.u64(self.instruction_number as u64);
+        self.call_stack.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Error> {
+        self.registers.load_state(state)?;
+        self.interrupts.load_state(state)?;
+        let locked = state.bool()?;
+        let lock = (state.u16()?, state.u8()?);
+        self.locked = locked.then_some(lock);
+        self.instruction_number = state.u64()? as u128;
+        self.call_stack.load_state(state)?;
+        self.operands = None;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        apu::Apu,
+        cpu::bus::{Access, RecordingBus},
+        interrupt_controller::InterruptController,
+        joypad::{Button, Joypad},
+        mmu::{Mmu, cartridge::Cartridge, mbc::NoMbc, memory::Memory},
+        ppu::Ppu,
+        serial::SerialPort,
+        timer::Timer,
+    };
+
+    // runs code from WRAM so no cartridge is needed
+    fn setup(code: &[u8]) -> (Cpu, Mmu, Debug) {
+        let mut mmu = Mmu::new(
+            Memory::new(),
+            None,
+            Timer::new(),
+            Ppu::new(),
+            Joypad::new(),
+            InterruptController::new(),
+            SerialPort::new(),
+            Apu::new(),
+        );
+        for (i, byte) in code.iter().enumerate() {
+            mmu.write_8(0xC000 + i as u16, *byte);
+        }
+        mmu.interrupts.iflag = 0xE0;
+
+        let mut cpu = Cpu::new();
+        cpu.registers.pc = 0xC000;
+        cpu.registers.sp = 0xD000;
+        (cpu, mmu, Debug::new())
+    }
+
+    // maps a ROM-only cartridge with `code` at 0x0000
+    fn insert_rom(mmu: &mut Mmu, code: &[u8]) {
+        let mut rom = vec![0u8; 0x8000];
+        rom[..code.len()].copy_from_slice(code);
+        mmu.cartridge = Some(Cartridge {
+            rom,
+            ram: Vec::new(),
+            mbc: Box::new(NoMbc::new()),
+            cgb: false,
+            save_path: None,
+            ram_dirty: false,
+        });
+    }
+
+    fn request_timer(mmu: &mut Mmu) {
+        mmu.interrupts.ie = Interrupt::Timer.bit();
+        mmu.interrupts.request(Interrupt::Timer);
+    }
+
+    #[test]
+    fn conditional_branch_cycles() {
+        // JR NZ,+0 ; JR Z,+0
+        let (mut cpu, mut mmu, mut debug) = setup(&[0x20, 0x00, 0x28, 0x00]);
+        cpu.registers.f = 0;
+        assert_eq!(cpu.step(&mut mmu, &mut debug), 12);
+        assert_eq!(cpu.step(&mut mmu, &mut debug), 8);
+        assert_eq!(cpu.registers.pc, 0xC004);
+    }
+
+    #[test]
+    fn prefix_reports_cb_cycles() {
+        // BIT 0,(HL) ; SET 0,(HL) ; SWAP A
+        let (mut cpu, mut mmu, mut debug) = setup(&[0xCB, 0x46, 0xCB, 0xC6, 0xCB, 0x37]);
+        cpu.registers.set_hl(0xC100);
+        assert_eq!(cpu.step(&mut mmu, &mut debug), 12);
+        assert_eq!(cpu.step(&mut mmu, &mut debug), 16);
+        assert_eq!(cpu.step(&mut mmu, &mut debug), 8);
+        assert_eq!(cpu.registers.pc, 0xC006);
+    }
+
+    #[test]
+    fn halt_bug_repeats_next_byte() {
+        // HALT ; INC A ; NOP
+        let (mut cpu, mut mmu, mut debug) = setup(&[0x76, 0x3C, 0x00]);
+        cpu.registers.a = 0;
+        request_timer(&mut mmu);
+
+        cpu.step(&mut mmu, &mut debug);
+        assert!(!cpu.interrupts.halted);
+        assert_eq!(cpu.registers.pc, 0xC001);
+
+        cpu.step(&mut mmu, &mut debug);
+        assert_eq!(cpu.registers.pc, 0xC001);
+        cpu.step(&mut mmu, &mut debug);
+        assert_eq!(cpu.registers.pc, 0xC002);
+        assert_eq!(cpu.registers.a, 2);
+    }
+
+    #[test]
+    fn halt_bug_reads_opcode_as_operand() {
+        // HALT ; LD A,$14 -> executes LD A,$3E then INC D
+        let (mut cpu, mut mmu, mut debug) = setup(&[0x76, 0x3E, 0x14]);
+        cpu.registers.d = 0;
+        request_timer(&mut mmu);
+
+        cpu.step(&mut mmu, &mut debug);
+        cpu.step(&mut mmu, &mut debug);
+        assert_eq!(cpu.registers.a, 0x3E);
+        assert_eq!(cpu.registers.pc, 0xC002);
+        cpu.step(&mut mmu, &mut debug);
+        assert_eq!(cpu.registers.d, 1);
+    }
+
+    #[test]
+    fn halt_with_ime_off_wakes_without_servicing() {
+        // HALT ; INC A
+        let (mut cpu, mut mmu, mut debug) = setup(&[0x76, 0x3C]);
+        cpu.registers.a = 0;
+        mmu.interrupts.ie = Interrupt::Timer.bit();
+
+        cpu.step(&mut mmu, &mut debug);
+        assert!(cpu.interrupts.halted);
+        assert_eq!(cpu.step(&mut mmu, &mut debug), 4);
+        assert!(cpu.interrupts.halted);
+
+        mmu.interrupts.request(Interrupt::Timer);
+        cpu.step(&mut mmu, &mut debug);
+        assert!(!cpu.interrupts.halted);
+        assert_eq!(cpu.registers.a, 1);
+        assert_eq!(cpu.registers.pc, 0xC002);
+        // the request stays pending
+        assert_eq!(mmu.interrupts.pending_mask(), Interrupt::Timer.bit());
+    }
+
+    #[test]
+    fn halt_with_ime_on_services_interrupt() {
+        // HALT ; NOP
+        let (mut cpu, mut mmu, mut debug) = setup(&[0x76, 0x00]);
+        cpu.interrupts.ime = true;
+        mmu.interrupts.ie = Interrupt::Timer.bit();
+
+        cpu.step(&mut mmu, &mut debug);
+        assert!(cpu.interrupts.halted);
+
+        mmu.interrupts.request(Interrupt::Timer);
+        assert_eq!(cpu.step(&mut mmu, &mut debug), 20);
+        assert_eq!(cpu.registers.pc, Interrupt::Timer.vector());
+        assert_eq!(mmu.read_8(0xCFFF), 0xC0);
+        assert_eq!(mmu.read_8(0xCFFE), 0x01);
+    }
+
+    #[test]
+    fn stop_skips_second_byte() {
+        // STOP ; $00 ; INC A
+        let (mut cpu, mut mmu, mut debug) = setup(&[0x10, 0x00, 0x3C]);
+        cpu.registers.a = 0;
+
+        cpu.step(&mut mmu, &mut debug);
+        assert!(cpu.interrupts.stopped);
+        assert_eq!(cpu.registers.pc, 0xC002);
+
+        // nothing pressed, the CPU stays stopped
+        cpu.step(&mut mmu, &mut debug);
+        assert!(cpu.interrupts.stopped);
+        assert_eq!(cpu.registers.a, 0);
+
+        // a button on a selected line wakes it up again
+        mmu.joypad.press(Button::A, &mut mmu.interrupts);
+        cpu.step(&mut mmu, &mut debug);
+        assert!(!cpu.interrupts.stopped);
+        assert_eq!(cpu.registers.a, 1);
+    }
+
+    #[test]
+    fn stop_switches_speed_on_cgb() {
+        // STOP ; $00
+        let (mut cpu, mut mmu, mut debug) = setup(&[0x10, 0x00]);
+        mmu.cgb = true;
+        assert_eq!(mmu.read_8(0xFF4D), 0x7E);
+        mmu.write_8(0xFF4D, 0x01);
+        assert_eq!(mmu.read_8(0xFF4D), 0x7F);
+
+        cpu.step(&mut mmu, &mut debug);
+        assert!(!cpu.interrupts.stopped);
+        assert!(mmu.double_speed);
+        assert_eq!(mmu.read_8(0xFF4D), 0xFE);
+        assert_eq!(cpu.registers.pc, 0xC002);
+    }
+
+    #[test]
+    fn key1_is_unmapped_on_dmg() {
+        let (_, mut mmu, _) = setup(&[]);
+        mmu.write_8(0xFF4D, 0x01);
+        assert_eq!(mmu.read_8(0xFF4D), 0xFF);
+        assert!(!mmu.speed_switch_armed());
+    }
+
+    #[test]
+    fn illegal_opcode_locks_cpu() {
+        // NOP ; $D3 ; INC A
+        let (mut cpu, mut mmu, mut debug) = setup(&[0x00, 0xD3, 0x3C]);
+        cpu.registers.a = 0;
+        cpu.interrupts.ime = true;
+
+        cpu.step(&mut mmu, &mut debug);
+        cpu.step(&mut mmu, &mut debug);
+        assert_eq!(cpu.locked, Some((0xC001, 0xD3)));
+        assert_eq!(
+            cpu.lockup_message().unwrap(),
+            "CPU locked at $C001 by illegal opcode $D3"
+        );
+
+        request_timer(&mut mmu);
+        for _ in 0..4 {
+            assert_eq!(cpu.step(&mut mmu, &mut debug), 4);
+        }
+        assert_eq!(cpu.registers.pc, 0xC001);
+        assert_eq!(cpu.registers.a, 0);
+    }
+
+    #[test]
+    fn operands_wrap_past_top_of_memory() {
+        // LD A,n at $FFFE with the operand in IE, then INC A at $0000
+        let (mut cpu, mut mmu, mut debug) = setup(&[]);
+        insert_rom(&mut mmu, &[0x3C]);
+        mmu.write_8(0xFFFE, 0x3E);
+        mmu.write_8(0xFFFF, 0x41);
+        cpu.registers.pc = 0xFFFE;
+
+        cpu.step(&mut mmu, &mut debug);
+        assert_eq!(cpu.registers.a, 0x41);
+        assert_eq!(cpu.registers.pc, 0x0000);
+        cpu.step(&mut mmu, &mut debug);
+        assert_eq!(cpu.registers.a, 0x42);
+        assert_eq!(cpu.registers.pc, 0x0001);
+    }
+
+    #[test]
+    fn jumps_and_calls_wrap_around() {
+        // JR -2 at $0000 lands on $FFFE, CALL at $FFFE reads its target across the wrap
+        let (mut cpu, mut mmu, mut debug) = setup(&[]);
+        insert_rom(&mut mmu, &[0x18, 0xFC, 0xC0]);
+        mmu.write_8(0xFFFE, 0xCD);
+        mmu.write_8(0xFFFF, 0x00);
+        cpu.registers.pc = 0x0000;
+
+        cpu.step(&mut mmu, &mut debug);
+        assert_eq!(cpu.registers.pc, 0xFFFE);
+
+        // target: lo from $FFFF, hi from $0000
+        cpu.step(&mut mmu, &mut debug);
+        assert_eq!(cpu.registers.pc, 0x1800);
+        assert_eq!(mmu.read_8(0xCFFF), 0x00);
+        assert_eq!(mmu.read_8(0xCFFE), 0x01);
+    }
+
+    #[test]
+    fn stack_wraps_on_underflow_and_overflow() {
+        // PUSH BC ; POP DE ; POP HL
+        let (mut cpu, mut mmu, mut debug) = setup(&[0xC5, 0xD1, 0xE1]);
+        insert_rom(&mut mmu, &[0x12]);
+        cpu.registers.sp = 0x0000;
+        cpu.registers.set_bc(0xAB34);
+
+        // writes $FFFF (IE) and $FFFE (HRAM)
+        cpu.step(&mut mmu, &mut debug);
+        assert_eq!(cpu.registers.sp, 0xFFFE);
+        assert_eq!(mmu.read_8(0xFFFF), 0xAB);
+        assert_eq!(mmu.read_8(0xFFFE), 0x34);
+
+        cpu.step(&mut mmu, &mut debug);
+        assert_eq!(cpu.registers.de(), 0xAB34);
+        assert_eq!(cpu.registers.sp, 0x0000);
+
+        // pops from $0000 and $0001 in ROM
+        cpu.registers.sp = 0xFFFF;
+        cpu.step(&mut mmu, &mut debug);
+        assert_eq!(cpu.registers.hl(), 0x12AB);
+        assert_eq!(cpu.registers.sp, 0x0001);
+    }
+
+    #[test]
+    fn hl_increment_and_decrement_wrap() {
+        // LD (HL+),A ; LD A,(HL-) ; LD (nn),SP with nn = $FFFF
+        let (mut cpu, mut mmu, mut debug) = setup(&[0x22, 0x3A, 0x08, 0xFF, 0xFF]);
+        insert_rom(&mut mmu, &[]);
+        cpu.registers.set_hl(0xFFFF);
+        cpu.registers.a = 0x1F;
+
+        cpu.step(&mut mmu, &mut debug);
+        assert_eq!(cpu.registers.hl(), 0x0000);
+        assert_eq!(mmu.read_8(0xFFFF), 0x1F);
+
+        cpu.step(&mut mmu, &mut debug);
+        assert_eq!(cpu.registers.hl(), 0xFFFF);
+
+        cpu.registers.sp = 0x0102;
+        cpu.step(&mut mmu, &mut debug);
+        assert_eq!(mmu.read_8(0xFFFF), 0x02);
+    }
+
+    #[test]
+    fn step_runs_against_a_recording_bus() {
+        // CALL $C010
+        let (mut cpu, mmu, mut debug) = setup(&[0xCD, 0x10, 0xC0]);
+        let mut bus = RecordingBus::new(mmu);
+
+        assert_eq!(cpu.step(&mut bus, &mut debug), 24);
+        assert_eq!(
+            bus.accesses,
+            [
+                Access::Read(0xC000, 0xCD),
+                Access::Read(0xC001, 0x10),
+                Access::Read(0xC002, 0xC0),
+                Access::Idle,
+                Access::Write(0xCFFF, 0xC0),
+                Access::Write(0xCFFE, 0x03),
+            ]
+        );
+
+        // interrupt dispatch: two internal cycles, then the pushes
+        bus.accesses.clear();
+        cpu.interrupts.ime = true;
+        request_timer(&mut bus.inner);
+        assert_eq!(cpu.step(&mut bus, &mut debug), 20);
+        assert_eq!(
+            bus.accesses,
+            [
+                Access::Idle,
+                Access::Idle,
+                Access::Write(0xCFFD, 0xC0),
+                Access::Write(0xCFFC, 0x10),
+            ]
+        );
+        assert_eq!(bus.inner.interrupts.pending_mask(), 0);
+    }
+
+    #[test]
+    fn ei_is_delayed_by_one_instruction() {
+        // EI ; NOP ; NOP
+        let (mut cpu, mut mmu, mut debug) = setup(&[0xFB, 0x00, 0x00]);
+        request_timer(&mut mmu);
+
+        cpu.step(&mut mmu, &mut debug);
+        assert!(!cpu.interrupts.ime);
+        cpu.step(&mut mmu, &mut debug);
+        assert!(cpu.interrupts.ime);
+        assert_eq!(cpu.registers.pc, 0xC002);
+
+        assert_eq!(cpu.step(&mut mmu, &mut debug), 20);
+        assert_eq!(cpu.registers.pc, Interrupt::Timer.vector());
+    }
+
+    #[test]
+    fn ei_di_never_enables_interrupts() {
+        // EI ; DI ; NOP
+        let (mut cpu, mut mmu, mut debug) = setup(&[0xFB, 0xF3, 0x00]);
+        request_timer(&mut mmu);
+
+        cpu.step(&mut mmu, &mut debug);
+        cpu.step(&mut mmu, &mut debug);
+        cpu.step(&mut mmu, &mut debug);
+        assert!(!cpu.interrupts.ime);
+        assert_eq!(cpu.registers.pc, 0xC003);
+    }
+
+    #[test]
+    fn ei_halt_returns_to_halt() {
+        // EI ; HALT ; NOP
+        let (mut cpu, mut mmu, mut debug) = setup(&[0xFB, 0x76, 0x00]);
+        request_timer(&mut mmu);
+
+        assert_eq!(cpu.step(&mut mmu, &mut debug), 4);
+        assert_eq!(cpu.step(&mut mmu, &mut debug), 4);
+        assert!(!cpu.interrupts.halted);
+        assert!(cpu.interrupts.ime);
+
+        // serviced right away, with the HALT as return address
+        assert_eq!(cpu.step(&mut mmu, &mut debug), 20);
+        assert_eq!(cpu.registers.pc, Interrupt::Timer.vector());
+        assert_eq!(mmu.read_8(0xCFFF), 0xC0);
+        assert_eq!(mmu.read_8(0xCFFE), 0x01);
+    }
+
+    #[test]
+    fn call_stack_follows_calls_interrupts_and_returns() {
+        // CALL C004 ; NOP ; EI ; NOP ; RET
+        let (mut cpu, mut mmu, mut debug) = setup(&[0xCD, 0x04, 0xC0, 0x00, 0xFB, 0x00, 0xC9]);
+        let mut rom = [0u8; 0x51];
+        rom[Interrupt::Timer.vector() as usize] = 0xD9; // RETI
+        insert_rom(&mut mmu, &rom);
+
+        cpu.step(&mut mmu, &mut debug);
+        let frame = cpu.call_stack.frames[0];
+        assert_eq!(frame.kind, FrameKind::Call);
+        assert_eq!(frame.function.addr, 0xC004);
+        assert_eq!((frame.caller, frame.return_addr), (0xC000, 0xC003));
+
+        cpu.step(&mut mmu, &mut debug);
+        cpu.step(&mut mmu, &mut debug);
+        request_timer(&mut mmu);
+        cpu.step(&mut mmu, &mut debug);
+        assert_eq!(cpu.call_stack.frames.len(), 2);
+        assert_eq!(cpu.call_stack.frames[1].kind, FrameKind::Interrupt);
+
+        // RETI from the handler, then RET from the call
+        cpu.step(&mut mmu, &mut debug);
+        cpu.step(&mut mmu, &mut debug);
+        assert_eq!(cpu.registers.pc, 0xC003);
+        assert!(cpu.call_stack.frames.is_empty());
+        assert_eq!(cpu.call_stack.warning, None);
+    }
+
+    #[test]
+    fn cached_code_sees_self_modifying_writes() {
+        // LD A,11 ; JR -4
+        let (mut cpu, mut mmu, mut debug) = setup(&[0x3E, 0x11, 0x18, 0xFC]);
+        cpu.step(&mut mmu, &mut debug);
+        cpu.step(&mut mmu, &mut debug);
+        assert_eq!((cpu.registers.a, cpu.registers.pc), (0x11, 0xC000));
+
+        mmu.write_8(0xC001, 0x22);
+        cpu.step(&mut mmu, &mut debug);
+        assert_eq!(cpu.registers.a, 0x22);
     }
 }
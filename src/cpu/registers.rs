@@ -1,3 +1,7 @@
+use std::io::Error;
+
+use crate::state::{SaveState, StateReader, StateWriter};
+
 #[derive(Clone, Copy)]
 pub enum Flags {
     Z = 0b1000_0000,
@@ -12,6 +16,7 @@ impl Flags {
     }
 }
 
+#[derive(Clone, Debug, PartialEq)]
 pub struct Registers {
     pub a: u8,
     pub f: u8, // flags
@@ -103,6 +108,42 @@ impl Registers {
         self.pc = 0x0100;
     }
 }
+
+impl Default for Registers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl SaveState for Registers {
+    fn save_state(&self, state: &mut StateWriter) {
+        for value in [
+            self.a, self.f, self.b, self.c, self.d, self.e, self.h, self.l,
+        ] {
+            state.u8(value);
+        }
+        state.u16(self.sp);
+        state.u16(self.pc);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Error> {
+        for value in [
+            &mut self.a,
+            &mut self.f,
+            &mut self.b,
+            &mut self.c,
+            &mut self.d,
+            &mut self.e,
+            &mut self.h,
+            &mut self.l,
+        ] {
+            *value = state.u8()?;
+        }
+        self.sp = state.u16()?;
+        self.pc = state.u16()?;
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -176,23 +217,23 @@ mod tests {
     fn test_get_flag_set() {
         let mut registers = Registers::new();
         registers.set_flags(false, false, true, false);
-        assert_eq!(registers.get_flag(Flags::H), true);
-        assert_eq!(registers.get_flag(Flags::Z), false);
+        assert!(registers.get_flag(Flags::H));
+        assert!(!registers.get_flag(Flags::Z));
     }
 
     #[test]
     fn test_get_flag_clear() {
         let mut registers = Registers::new();
         registers.set_flags(false, true, false, false);
-        assert_eq!(registers.get_flag(Flags::N), true);
+        assert!(registers.get_flag(Flags::N));
     }
 
     #[test]
     fn test_multiple_flag_operations() {
         let mut registers = Registers::new();
         registers.set_flags(true, true, true, true);
-        assert_eq!(registers.get_flag(Flags::Z), true);
-        assert_eq!(registers.get_flag(Flags::N), true);
+        assert!(registers.get_flag(Flags::Z));
+        assert!(registers.get_flag(Flags::N));
     }
     #[test]
     fn test_reset_to_dmg_state() {
@@ -222,8 +263,8 @@ mod tests {
     fn test_set_flag_method() {
         let mut registers = Registers::new();
         registers.set_flag(Flags::Z, true);
-        assert_eq!(registers.get_flag(Flags::Z), true);
+        assert!(registers.get_flag(Flags::Z));
         registers.set_flag(Flags::Z, false);
-        assert_eq!(registers.get_flag(Flags::Z), false);
+        assert!(!registers.get_flag(Flags::Z));
     }
 }
@@ -1 +1,97 @@
+use std::fs::File;
+use std::io::{BufWriter, Error, Write};
 
+use crate::cpu::{Cpu, bus::Bus};
+
+// Streams one line per executed instruction in the Gameboy Doctor layout
+// (https://github.com/robert/gameboy-doctor), so runs can be diffed against
+// known-good logs.
+pub struct TraceLogger {
+    writer: BufWriter<File>,
+    // the first failed write, nothing is logged after it
+    error: Option<Error>,
+}
+
+impl TraceLogger {
+    pub fn new(path: &str) -> Result<Self, Error> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+            error: None,
+        })
+    }
+
+    // a failure is kept for `flush` to report, the trace would have gaps
+    pub fn log<B: Bus>(&mut self, cpu: &Cpu, bus: &B) {
+        if self.error.is_some() {
+            return;
+        }
+        if let Err(e) = writeln!(self.writer, "{}", doctor_line(cpu, bus)) {
+            self.error = Some(e);
+        }
+    }
+
+    pub fn flush(&mut self) -> Result<(), Error> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.writer.flush()
+    }
+}
+
+pub fn doctor_line<B: Bus>(cpu: &Cpu, bus: &B) -> String {
+    let r = &cpu.registers;
+    let pc = r.pc;
+    format!(
+        "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} \
+        SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+        r.a,
+        r.f,
+        r.b,
+        r.c,
+        r.d,
+        r.e,
+        r.h,
+        r.l,
+        r.sp,
+        pc,
+        bus.peek(pc),
+        bus.peek(pc.wrapping_add(1)),
+        bus.peek(pc.wrapping_add(2)),
+        bus.peek(pc.wrapping_add(3)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Emulator;
+
+    #[test]
+    fn formats_gameboy_doctor_line() {
+        let mut emulator = Emulator::new();
+        emulator.cpu.registers.reset();
+        emulator.cpu.registers.pc = 0xC000;
+        for (i, byte) in [0x00, 0xC3, 0x13, 0x02].iter().enumerate() {
+            emulator.mmu.write_8(0xC000 + i as u16, *byte);
+        }
+
+        assert_eq!(
+            doctor_line(&emulator.cpu, &emulator.mmu),
+            "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:C000 PCMEM:00,C3,13,02"
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn write_errors_are_reported_on_flush() {
+        let mut emulator = Emulator::new();
+        emulator.cpu.registers.pc = 0xC000;
+        let mut trace = TraceLogger::new("/dev/full").unwrap();
+        // past what the writer buffers
+        for _ in 0..1000 {
+            trace.log(&emulator.cpu, &emulator.mmu);
+        }
+        assert!(trace.error.is_some());
+        assert!(trace.flush().is_err());
+    }
+}
@@ -1,9 +1,49 @@
 pub mod disasm;
 pub mod logger;
+pub mod profiler;
+pub mod reverse;
+pub mod runner;
+pub mod symbols;
+
+use std::fmt;
+
+use crate::cpu::bus::Bus;
+use logger::TraceLogger;
+use profiler::Profiler;
+use symbols::Symbols;
+
+// Address of an instruction together with the ROM bank it was executed from,
+// so code at the same address in different banks is counted separately
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Location {
+    pub bank: u16,
+    pub addr: u16,
+}
+
+impl Location {
+    pub fn of<B: Bus>(bus: &B, addr: u16) -> Self {
+        Self {
+            bank: bus.rom_bank(addr),
+            addr,
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02X}:{:04X}", self.bank, self.addr)
+    }
+}
 
 pub struct Debug {
     pub log_cpu: bool,
     pub breakpoints: Vec<u16>,
+    pub trace: Option<TraceLogger>,
+    pub profiler: Option<Profiler>,
+    pub symbols: Symbols,
+    pub break_on_trap: bool,
+    break_requested: bool,
+    trap_hit: bool,
 }
 
 impl Debug {
@@ -11,6 +51,12 @@ impl Debug {
         Self {
             log_cpu: false,
             breakpoints: Vec::new(),
+            trace: None,
+            profiler: None,
+            symbols: Symbols::new(),
+            break_on_trap: false,
+            break_requested: false,
+            trap_hit: false,
         }
     }
 
@@ -27,5 +73,30 @@ impl Debug {
     // this breaks execution of the application, allows developer to put breakpoint
     // inside code, but it stops execution in natural way
     // with switch of execution type
-    pub fn break_execution(&mut self) {}
+    pub fn break_execution(&mut self) {
+        self.break_requested = true;
+    }
+
+    pub fn take_break_request(&mut self) -> bool {
+        std::mem::take(&mut self.break_requested)
+    }
+
+    // LD B,B does nothing on hardware, test ROMs (mooneye) and debuggers
+    // like BGB use it as a software breakpoint
+    pub fn trap(&mut self) {
+        self.trap_hit = true;
+        if self.break_on_trap {
+            self.break_execution();
+        }
+    }
+
+    pub fn take_trap(&mut self) -> bool {
+        std::mem::take(&mut self.trap_hit)
+    }
+}
+
+impl Default for Debug {
+    fn default() -> Self {
+        Self::new()
+    }
 }
@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+use std::mem::{Discriminant, discriminant};
+
+use crate::cpu::call_stack::Frame;
+use crate::cpu::decoder::Opcode;
+use crate::debug::Location;
+
+// how many entries each section of the report lists
+const REPORT_ROWS: usize = 10;
+
+#[derive(Default)]
+struct Stats {
+    count: u64,
+    cycles: u64,
+}
+
+#[derive(Default)]
+struct FunctionStats {
+    calls: u64,
+    self_cycles: u64,
+    total_cycles: u64,
+}
+
+pub struct Profiler {
+    start_instruction: u128,
+    cycles: u64,
+    addresses: HashMap<Location, Stats>,
+    opcodes: HashMap<Discriminant<Opcode>, (String, Stats)>,
+    // backward jumps, keyed by (loop head, jump instruction)
+    loops: HashMap<(Location, Location), u64>,
+    functions: HashMap<Location, FunctionStats>,
+}
+
+impl Profiler {
+    pub fn new(instruction_number: u128) -> Self {
+        Self {
+            start_instruction: instruction_number,
+            cycles: 0,
+            addresses: HashMap::new(),
+            opcodes: HashMap::new(),
+            loops: HashMap::new(),
+            functions: HashMap::new(),
+        }
+    }
+
+    // `taken` tells whether the instruction left PC somewhere else than
+    // right after itself, `next` is where execution continues. Time goes to
+    // the CPU's call stack as it is after the instruction, a CALL counts
+    // towards the function it enters and a RET towards the one it returns to.
+    pub fn record(
+        &mut self,
+        at: Location,
+        opcode: &Opcode,
+        cycles: u32,
+        taken: bool,
+        next: Location,
+        frames: &[Frame],
+    ) {
+        let cycles = cycles as u64;
+        self.cycles += cycles;
+
+        let stats = self.addresses.entry(at).or_default();
+        stats.count += 1;
+        stats.cycles += cycles;
+
+        let (_, stats) = self
+            .opcodes
+            .entry(discriminant(opcode))
+            .or_insert_with(|| (variant_name(opcode), Stats::default()));
+        stats.count += 1;
+        stats.cycles += cycles;
+
+        match opcode {
+            Opcode::CallN16 | Opcode::Rst(_) => self.enter(next),
+            Opcode::CallCCN16(_) if taken => self.enter(next),
+            Opcode::JrE8 | Opcode::JrCCE8(_) | Opcode::JpN16 | Opcode::JpCCN16(_)
+                if taken && next.bank == at.bank && next.addr <= at.addr =>
+            {
+                *self.loops.entry((next, at)).or_default() += 1;
+            }
+            _ => {}
+        }
+
+        self.attribute(cycles, frames);
+    }
+
+    // interrupt dispatch behaves like a CALL to the vector
+    pub fn interrupt(&mut self, vector: Location, cycles: u32, frames: &[Frame]) {
+        self.cycles += cycles as u64;
+        self.enter(vector);
+        self.attribute(cycles as u64, frames);
+    }
+
+    fn enter(&mut self, function: Location) {
+        self.functions.entry(function).or_default().calls += 1;
+    }
+
+    fn attribute(&mut self, cycles: u64, frames: &[Frame]) {
+        let Some(current) = frames.last() else {
+            return;
+        };
+        self.functions
+            .entry(current.function)
+            .or_default()
+            .self_cycles += cycles;
+
+        // recursive functions only count once towards their total
+        for (i, frame) in frames.iter().enumerate() {
+            if !frames[..i].iter().any(|f| f.function == frame.function) {
+                self.functions
+                    .entry(frame.function)
+                    .or_default()
+                    .total_cycles += cycles;
+            }
+        }
+    }
+
+    pub fn report(&self, instruction_number: u128) -> String {
+        let instructions = instruction_number.saturating_sub(self.start_instruction);
+        let mut report = format!(
+            "Profiled {} instructions, {} cycles\n",
+            instructions, self.cycles
+        );
+
+        report += "\nHot loops (head-jump, iterations, body cycles)\n";
+        let mut loops: Vec<_> = self
+            .loops
+            .iter()
+            .map(|(&(head, jump), &iterations)| {
+                (head, jump, iterations, self.cycles_in(head, jump))
+            })
+            .collect();
+        loops.sort_by(|a, b| b.3.cmp(&a.3).then(a.0.cmp(&b.0)));
+        for (head, jump, iterations, cycles) in loops.iter().take(REPORT_ROWS) {
+            report += &format!(
+                "  {}-{:04X}  {:>10}  {:>12}\n",
+                head, jump.addr, iterations, cycles
+            );
+        }
+
+        report += "\nHottest functions (entry, calls, self cycles, total cycles)\n";
+        let mut functions: Vec<_> = self.functions.iter().collect();
+        functions.sort_by(|a, b| b.1.total_cycles.cmp(&a.1.total_cycles).then(a.0.cmp(b.0)));
+        for (function, stats) in functions.iter().take(REPORT_ROWS) {
+            report += &format!(
+                "  {}  {:>10}  {:>12}  {:>12}\n",
+                function, stats.calls, stats.self_cycles, stats.total_cycles
+            );
+        }
+
+        report += "\nOpcodes (variant, count, cycles, share)\n";
+        let mut opcodes: Vec<_> = self.opcodes.values().collect();
+        opcodes.sort_by(|a, b| b.1.count.cmp(&a.1.count).then(a.0.cmp(&b.0)));
+        for (name, stats) in opcodes {
+            let share = stats.count as f64 * 100.0 / instructions.max(1) as f64;
+            report += &format!(
+                "  {:<12}  {:>10}  {:>12}  {:>6.2}%\n",
+                name, stats.count, stats.cycles, share
+            );
+        }
+
+        report
+    }
+
+    fn cycles_in(&self, head: Location, jump: Location) -> u64 {
+        self.addresses
+            .iter()
+            .filter(|(at, _)| at.bank == head.bank && (head.addr..=jump.addr).contains(&at.addr))
+            .map(|(_, stats)| stats.cycles)
+            .sum()
+    }
+}
+
+// "LdR8R8(B, C)" -> "LdR8R8"
+fn variant_name(opcode: &Opcode) -> String {
+    let name = format!("{:?}", opcode);
+    match name.find('(') {
+        Some(end) => name[..end].to_string(),
+        None => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::call_stack::FrameKind;
+    use crate::cpu::decoder::{CC, R8};
+
+    fn at(addr: u16) -> Location {
+        Location { bank: 1, addr }
+    }
+
+    fn frame(function: Location) -> Frame {
+        Frame {
+            kind: FrameKind::Call,
+            function,
+            caller: 0x4000,
+            return_addr: 0x4003,
+            sp: 0xDFFE,
+        }
+    }
+
+    #[test]
+    fn counts_loops_functions_and_opcodes() {
+        let mut profiler = Profiler::new(0);
+        let called = [frame(at(0x4100))];
+
+        // CALL 4100 from 4000
+        profiler.record(at(0x4000), &Opcode::CallN16, 24, true, at(0x4100), &called);
+        for _ in 0..3 {
+            // DEC B; JR NZ,4100
+            let dec = Opcode::DecR8(R8::B);
+            profiler.record(at(0x4100), &dec, 4, false, at(0x4101), &called);
+            let jr = Opcode::JrCCE8(CC::NZ);
+            profiler.record(at(0x4101), &jr, 12, true, at(0x4100), &called);
+        }
+        profiler.record(at(0x4103), &Opcode::Ret, 16, true, at(0x4003), &[]);
+        profiler.record(at(0x4003), &Opcode::Nop, 4, false, at(0x4004), &[]);
+
+        assert_eq!(profiler.cycles, 24 + 3 * 16 + 16 + 4);
+        assert_eq!(profiler.loops[&(at(0x4100), at(0x4101))], 3);
+        assert_eq!(profiler.cycles_in(at(0x4100), at(0x4101)), 48);
+
+        let function = &profiler.functions[&at(0x4100)];
+        assert_eq!(function.calls, 1);
+        assert_eq!(function.self_cycles, 24 + 3 * 16);
+        assert_eq!(function.total_cycles, 24 + 3 * 16);
+
+        let (name, stats) = &profiler.opcodes[&discriminant(&Opcode::DecR8(R8::A))];
+        assert_eq!(name, "DecR8");
+        assert_eq!(stats.count, 3);
+
+        let report = profiler.report(9);
+        assert!(report.contains("Profiled 9 instructions"));
+        assert!(report.contains("01:4100-4101"));
+    }
+
+    #[test]
+    fn recursion_counts_once_towards_the_total() {
+        let mut profiler = Profiler::new(0);
+        let frames = [frame(at(0x4100)), frame(at(0x4200)), frame(at(0x4100))];
+        profiler.record(at(0x4100), &Opcode::Nop, 4, false, at(0x4101), &frames);
+        assert_eq!(profiler.functions[&at(0x4100)].total_cycles, 4);
+        assert_eq!(profiler.functions[&at(0x4100)].self_cycles, 4);
+        assert_eq!(profiler.functions[&at(0x4200)].self_cycles, 0);
+        assert_eq!(profiler.functions[&at(0x4200)].total_cycles, 4);
+    }
+
+    #[test]
+    fn banks_are_profiled_separately() {
+        let mut profiler = Profiler::new(0);
+        let other = Location {
+            bank: 2,
+            addr: 0x4000,
+        };
+        profiler.record(at(0x4000), &Opcode::Nop, 4, false, at(0x4001), &[]);
+        profiler.record(other, &Opcode::Nop, 4, false, at(0x4001), &[]);
+        assert_eq!(profiler.addresses.len(), 2);
+    }
+}
@@ -0,0 +1,150 @@
+use std::io::Error;
+
+use crate::emulator::Emulator;
+use crate::rewind::Rewind;
+
+// Reverse execution on top of the rewind buffer. Emulation is deterministic,
+// so any earlier tick can be reached again by restoring the newest capture
+// before it and running forward from there.
+
+enum Target {
+    // the last tick that executed an instruction
+    Instruction,
+    // the last tick that started on a breakpoint
+    Breakpoint(Vec<u16>),
+}
+
+// Goes back to right before the last executed instruction. Interrupt
+// dispatches and HALT idling in between are undone along with it.
+pub fn step_back(emulator: &mut Emulator) -> Result<(), Error> {
+    match find(emulator, Target::Instruction)? {
+        true => Ok(()),
+        false => Err(Error::other("no earlier instruction in the rewind buffer")),
+    }
+}
+
+// Goes back to the previous breakpoint hit. Without one execution ends up
+// at the oldest capture, returns whether a breakpoint was hit.
+pub fn reverse_continue(emulator: &mut Emulator) -> Result<bool, Error> {
+    let breakpoints = emulator.debug.breakpoints.clone();
+    find(emulator, Target::Breakpoint(breakpoints))
+}
+
+fn find(emulator: &mut Emulator, target: Target) -> Result<bool, Error> {
+    if emulator.movie.is_some() {
+        return Err(Error::other("can't reverse while a movie is running"));
+    }
+    if emulator.rewind.is_none() {
+        return Err(Error::other("reverse execution needs rewind to be on"));
+    }
+
+    // the debugger must not see the replay, no tracing, logging or
+    // breaking halfway
+    let debug = std::mem::take(&mut emulator.debug);
+    let result = search(emulator, &target);
+    emulator.debug = debug;
+    result
+}
+
+enum Scan {
+    Hit(u64),
+    // no hit, the position of the oldest capture searched
+    Miss(u64),
+    // no capture before the current tick
+    Empty,
+}
+
+// Scans back one capture interval at a time, nearest first. The buffer is
+// only cut back once there is somewhere to go.
+fn search(emulator: &mut Emulator, target: &Target) -> Result<bool, Error> {
+    let now = emulator.snapshot();
+    let Some(rewind) = emulator.rewind.take() else {
+        return Err(Error::other("reverse execution needs rewind to be on"));
+    };
+    let scanned = scan(emulator, &rewind, target);
+    emulator.rewind = Some(rewind);
+
+    match scanned {
+        Ok(Scan::Hit(hit)) => {
+            seek(emulator, hit)?;
+            replay(emulator, hit);
+            Ok(true)
+        }
+        // reverse-continue without a hit ends on the oldest capture
+        Ok(Scan::Miss(oldest)) if matches!(target, Target::Breakpoint(_)) => {
+            seek(emulator, oldest)?;
+            Ok(false)
+        }
+        // anything else leaves the machine where it was
+        Ok(Scan::Miss(_)) => {
+            emulator.restore(&now)?;
+            Ok(false)
+        }
+        Ok(Scan::Empty) => {
+            emulator.restore(&now)?;
+            Err(Error::other("no capture that far back"))
+        }
+        Err(e) => {
+            emulator.restore(&now)?;
+            Err(e)
+        }
+    }
+}
+
+fn scan(emulator: &mut Emulator, rewind: &Rewind, target: &Target) -> Result<Scan, Error> {
+    let mut until = emulator.ticks;
+    let mut scan = Scan::Empty;
+    for (position, state) in rewind.captures() {
+        if position >= until {
+            continue;
+        }
+        emulator.restore(&state)?;
+        if let Some(hit) = last_hit(emulator, target, until) {
+            return Ok(Scan::Hit(hit));
+        }
+        scan = Scan::Miss(position);
+        until = position;
+    }
+    Ok(scan)
+}
+
+// restores the newest capture at or before `ticks`, dropping later ones
+fn seek(emulator: &mut Emulator, ticks: u64) -> Result<Option<u64>, Error> {
+    let Some(rewind) = &mut emulator.rewind else {
+        return Ok(None);
+    };
+    let Some((position, state)) = rewind.seek(ticks) else {
+        return Ok(None);
+    };
+    emulator.restore(&state)?;
+    Ok(Some(position))
+}
+
+// runs up to `until` and returns the last matching tick, the rewind buffer
+// is out of the emulator so nothing gets captured
+fn last_hit(emulator: &mut Emulator, target: &Target, until: u64) -> Option<u64> {
+    let mut hit = None;
+    while emulator.ticks < until {
+        let tick = emulator.ticks;
+        let instructions = emulator.cpu.instruction_number;
+        if let Target::Breakpoint(breakpoints) = target
+            && breakpoints.contains(&emulator.cpu.registers.pc)
+        {
+            hit = Some(tick);
+        }
+        emulator.tick();
+        if let Target::Instruction = target
+            && emulator.cpu.instruction_number != instructions
+        {
+            hit = Some(tick);
+        }
+    }
+    hit
+}
+
+// captures are taken along the way, the buffer stays complete
+fn replay(emulator: &mut Emulator, until: u64) {
+    while emulator.ticks < until {
+        emulator.tick();
+    }
+}
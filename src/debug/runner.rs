@@ -0,0 +1,251 @@
+use std::fs;
+use std::io::Error;
+use std::path::{Path, PathBuf};
+
+use crate::cpu::registers::Registers;
+use crate::emulator::{CLOCK_SPEED, Emulator};
+
+// roughly a minute of emulated DMG time, enough for the slowest blargg ROMs
+pub const DEFAULT_CYCLE_BUDGET: u64 = CLOCK_SPEED * 60;
+
+// mooneye ROMs are short, most of them finish within a couple of frames
+pub const MOONEYE_CYCLE_BUDGET: u64 = CLOCK_SPEED * 10;
+
+// mooneye ROMs end with LD B,B, leaving this fibonacci signature in
+// B, C, D, E, H and L on success and 0x42 everywhere on failure
+const MOONEYE_SIGNATURE: [u8; 6] = [3, 5, 8, 13, 21, 34];
+
+#[derive(Debug, PartialEq)]
+pub enum TestResult {
+    Passed,
+    Failed,
+    Timeout,
+    // the ROM couldn't be loaded, the report's output says why
+    Error,
+}
+
+pub struct TestReport {
+    pub result: TestResult,
+    pub output: String,
+    pub cycles: u64,
+}
+
+impl TestReport {
+    pub fn summary(&self) -> String {
+        format!("{:?} after {} cycles", self.result, self.cycles)
+    }
+}
+
+// Boots a blargg style test ROM without a frontend and runs it until the
+// serial output reports a verdict or the cycle budget runs out
+pub fn run_test_rom(path: &str, budget: u64) -> Result<TestReport, Error> {
+    let mut emulator = Emulator::new();
+    emulator.load_rom(path)?;
+    emulator.reset()?;
+    Ok(run(&mut emulator, budget))
+}
+
+pub fn run(emulator: &mut Emulator, budget: u64) -> TestReport {
+    let mut cycles = 0;
+    // only rescan the output when a new character arrived
+    let mut seen = 0;
+
+    let result = loop {
+        if cycles >= budget {
+            break TestResult::Timeout;
+        }
+        cycles += emulator.tick() as u64;
+
+        let output = &emulator.mmu.serial.output;
+        if output.len() != seen {
+            seen = output.len();
+            if output.contains("Passed") {
+                break TestResult::Passed;
+            }
+            if output.contains("Failed") {
+                break TestResult::Failed;
+            }
+        }
+    };
+
+    TestReport {
+        result,
+        output: emulator.mmu.serial.output.clone(),
+        cycles,
+    }
+}
+
+pub fn run_mooneye_rom(path: &str, budget: u64) -> Result<TestReport, Error> {
+    let mut emulator = Emulator::new();
+    emulator.load_rom(path)?;
+    emulator.reset()?;
+    Ok(run_mooneye(&mut emulator, budget))
+}
+
+pub fn run_mooneye(emulator: &mut Emulator, budget: u64) -> TestReport {
+    let mut cycles = 0;
+    // a stale trap from before the run must not end it immediately
+    emulator.debug.take_trap();
+
+    let result = loop {
+        if cycles >= budget {
+            break TestResult::Timeout;
+        }
+        cycles += emulator.tick() as u64;
+
+        if emulator.debug.take_trap() {
+            break mooneye_verdict(&emulator.cpu.registers);
+        }
+    };
+
+    TestReport {
+        result,
+        output: emulator.mmu.serial.output.clone(),
+        cycles,
+    }
+}
+
+pub fn mooneye_verdict(registers: &Registers) -> TestResult {
+    let r = registers;
+    if [r.b, r.c, r.d, r.e, r.h, r.l] == MOONEYE_SIGNATURE {
+        TestResult::Passed
+    } else {
+        TestResult::Failed
+    }
+}
+
+// Runs every .gb ROM below dir, sorted by path so tables can be diffed
+pub fn run_mooneye_dir(dir: &str, budget: u64) -> Result<Vec<(String, TestReport)>, Error> {
+    let mut roms = Vec::new();
+    collect_roms(Path::new(dir), &mut roms)?;
+    roms.sort();
+
+    let mut reports = Vec::new();
+    for rom in roms {
+        let path = rom.to_string_lossy().to_string();
+        // one broken ROM doesn't keep the rest from running
+        let report = run_mooneye_rom(&path, budget).unwrap_or_else(|e| TestReport {
+            result: TestResult::Error,
+            output: e.to_string(),
+            cycles: 0,
+        });
+        let name = rom
+            .strip_prefix(dir)
+            .unwrap_or(&rom)
+            .to_string_lossy()
+            .to_string();
+        reports.push((name, report));
+    }
+    Ok(reports)
+}
+
+fn collect_roms(dir: &Path, roms: &mut Vec<PathBuf>) -> Result<(), Error> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_roms(&path, roms)?;
+        } else if path.extension().is_some_and(|ext| ext == "gb") {
+            roms.push(path);
+        }
+    }
+    Ok(())
+}
+
+pub fn format_table(reports: &[(String, TestReport)]) -> String {
+    let width = reports
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0);
+    let mut table = String::new();
+    for (name, report) in reports {
+        let line = format!(
+            "{:<width$}  {:<7}  {:>10}",
+            name,
+            format!("{:?}", report.result),
+            report.cycles,
+        );
+        match report.result {
+            TestResult::Error => table += &format!("{}  {}\n", line, report.output),
+            _ => table += &format!("{}\n", line),
+        }
+    }
+
+    let passed = reports
+        .iter()
+        .filter(|(_, report)| report.result == TestResult::Passed)
+        .count();
+    table += &format!("{}/{} passed\n", passed, reports.len());
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mmu::cartridge::Cartridge;
+    use crate::mmu::mbc::NoMbc;
+
+    fn emulator_with(code: &[u8]) -> Emulator {
+        let mut rom = vec![0u8; 0x8000];
+        rom[0x100..0x100 + code.len()].copy_from_slice(code);
+        let mut emulator = Emulator::new();
+        emulator.mmu.cartridge = Some(Cartridge {
+            rom,
+            ram: Vec::new(),
+            mbc: Box::new(NoMbc::new()),
+            cgb: false,
+            save_path: None,
+            ram_dirty: false,
+        });
+        emulator
+    }
+
+    // LD B,n; LD C,n; LD D,n; LD E,n; LD H,n; LD L,n; LD B,B; JR -2
+    fn signature_rom(values: [u8; 6]) -> Vec<u8> {
+        let mut code = Vec::new();
+        for (op, value) in [0x06, 0x0E, 0x16, 0x1E, 0x26, 0x2E].iter().zip(values) {
+            code.extend_from_slice(&[*op, value]);
+        }
+        code.extend_from_slice(&[0x40, 0x18, 0xFE]);
+        code
+    }
+
+    #[test]
+    fn mooneye_fibonacci_signature_passes() {
+        let mut emulator = emulator_with(&signature_rom(MOONEYE_SIGNATURE));
+        let report = run_mooneye(&mut emulator, 1_000);
+        assert_eq!(report.result, TestResult::Passed);
+    }
+
+    #[test]
+    fn mooneye_failure_signature_fails() {
+        let mut emulator = emulator_with(&signature_rom([0x42; 6]));
+        let report = run_mooneye(&mut emulator, 1_000);
+        assert_eq!(report.result, TestResult::Failed);
+    }
+
+    #[test]
+    fn mooneye_without_trap_times_out() {
+        // JR -2
+        let mut emulator = emulator_with(&[0x18, 0xFE]);
+        let report = run_mooneye(&mut emulator, 1_000);
+        assert_eq!(report.result, TestResult::Timeout);
+    }
+
+    #[test]
+    fn mooneye_dir_reports_unloadable_roms() {
+        let dir = std::env::temp_dir().join(format!("mooneye-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // too short for a header
+        fs::write(dir.join("broken.gb"), [0u8; 16]).unwrap();
+        let mut rom = vec![0u8; 0x8000];
+        rom[0x100..0x102].copy_from_slice(&[0x18, 0xFE]);
+        fs::write(dir.join("loop.gb"), rom).unwrap();
+
+        let reports = run_mooneye_dir(dir.to_str().unwrap(), 1_000).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let results: Vec<_> = reports.iter().map(|(_, r)| &r.result).collect();
+        assert_eq!(results, [&TestResult::Error, &TestResult::Timeout]);
+        assert!(format_table(&reports).contains("ROM size invalid!"));
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Error;
+
+use crate::debug::Location;
+
+// Symbol table in the RGBDS/BGB .sym format, one "BB:AAAA Name" per line
+pub struct Symbols {
+    names: BTreeMap<Location, String>,
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Self {
+            names: BTreeMap::new(),
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Self, Error> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let mut symbols = Self::new();
+        for line in text.lines() {
+            // everything after ';' is a comment
+            let line = line.split(';').next().unwrap_or("");
+            let mut parts = line.split_whitespace();
+            let (Some(location), Some(name)) = (parts.next(), parts.next()) else {
+                continue;
+            };
+            let Some((bank, addr)) = location.split_once(':') else {
+                continue;
+            };
+            if let (Ok(bank), Ok(addr)) =
+                (u16::from_str_radix(bank, 16), u16::from_str_radix(addr, 16))
+            {
+                symbols
+                    .names
+                    .insert(Location { bank, addr }, name.to_string());
+            }
+        }
+        symbols
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    // closest symbol at or before the location in the same bank,
+    // as "Name" or "Name+offset"
+    pub fn describe(&self, location: Location) -> Option<String> {
+        let (found, name) = self.names.range(..=location).next_back()?;
+        if found.bank != location.bank {
+            return None;
+        }
+        match location.addr - found.addr {
+            0 => Some(name.clone()),
+            offset => Some(format!("{}+{:X}", name, offset)),
+        }
+    }
+}
+
+impl Default for Symbols {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_describes_symbols() {
+        let symbols = Symbols::parse(
+            "; File generated by rgblink\n\
+            00:0150 Main\n\
+            01:4000 Bank1Start ; comment\n\
+            garbage line\n",
+        );
+        assert_eq!(symbols.len(), 2);
+
+        let at = |bank, addr| Location { bank, addr };
+        assert_eq!(symbols.describe(at(0, 0x0150)).as_deref(), Some("Main"));
+        assert_eq!(symbols.describe(at(0, 0x0162)).as_deref(), Some("Main+12"));
+        assert_eq!(
+            symbols.describe(at(1, 0x4001)).as_deref(),
+            Some("Bank1Start+1")
+        );
+        assert_eq!(symbols.describe(at(0, 0x0100)), None);
+        // a bank without symbols does not borrow names from the one before
+        assert_eq!(symbols.describe(at(2, 0x4000)), None);
+    }
+}
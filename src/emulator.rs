@@ -1,34 +1,88 @@
-use std::io::Error;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
 
 use crate::apu::Apu;
 use crate::cpu::Cpu;
-use crate::debug::Debug;
+use crate::cpu::call_stack::FrameKind;
+use crate::debug::logger::TraceLogger;
+use crate::debug::profiler::Profiler;
+use crate::debug::symbols::Symbols;
+use crate::debug::{Debug, Location};
 use crate::interrupt_controller::InterruptController;
-use crate::joypad::Joypad;
+use crate::joypad::{Button, Joypad};
 use crate::mmu::Mmu;
 use crate::mmu::memory::Memory;
+use crate::movie::{Movie, MovieMode};
 use crate::ppu::Ppu;
+use crate::rewind::Rewind;
 use crate::serial::SerialPort;
+use crate::state::{SaveState, StateReader, StateWriter, invalid};
 use crate::timer::Timer;
+use crate::utils::crc32;
+
+// T-cycles per second of the DMG clock
+pub const CLOCK_SPEED: u64 = 4_194_304;
+
+// battery backed RAM that changed is written out about once a second of
+// emulated time, counted in cycles so it still happens with the LCD off
+const BATTERY_FLUSH_CYCLES: u64 = CLOCK_SPEED;
+
+// Save state layout
+//
+// "GBSS"
+// u16       format version, bumped whenever a component's layout changes
+// u32       CRC-32 of the ROM
+// u64       ticks since power on
+// ...       CPU, then the MMU and everything it owns
+// u32       CRC-32 of everything above, only in files
+const STATE_MAGIC: &[u8; 4] = b"GBSS";
+const STATE_VERSION: u16 = 6;
 
 pub struct Emulator {
     pub cpu: Cpu,
     pub mmu: Mmu,
     pub debug: Debug,
+    // calls to `tick` since power on, positions reverse execution
+    pub ticks: u64,
+    pub movie: Option<Movie>,
+    pub rewind: Option<Rewind>,
+    recording_path: Option<String>,
+    rom_path: Option<String>,
+    // CRC-32 of the loaded ROM, ties movies and save states to it
+    rom_checksum: u32,
+    cycles_since_flush: u64,
+    // cartridge clocks follow the host's instead of emulated time
+    rtc_host_time: bool,
 }
 
 impl Emulator {
     pub fn new() -> Self {
+        Self {
+            cpu: Cpu::new(),
+            mmu: Self::new_mmu(),
+            debug: Debug::new(),
+            ticks: 0,
+            movie: None,
+            rewind: None,
+            recording_path: None,
+            rom_path: None,
+            rom_checksum: 0,
+            cycles_since_flush: 0,
+            rtc_host_time: false,
+        }
+    }
+
+    fn new_mmu() -> Mmu {
         let memory = Memory::new();
 
-        let cpu = Cpu::new();
         let ppu = Ppu::new();
         let timer = Timer::new();
         let interrupt_controller = InterruptController::new();
         let joypad = Joypad::new();
         let serial = SerialPort::new();
         let apu = Apu::new();
-        let mmu = Mmu::new(
+        Mmu::new(
             memory,
             None,
             timer,
@@ -37,40 +91,407 @@ impl Emulator {
             interrupt_controller,
             serial,
             apu,
-        );
-
-        Self {
-            cpu,
-            mmu,
-            debug: Debug::new(),
-        }
+        )
     }
 
     pub fn tick(&mut self) -> u32 {
-        let cycles = self.cpu.step(&mut self.mmu, &self.debug);
+        // the PPU may finish a frame anywhere within the instruction
+        self.mmu.ppu.clear_frame_ready();
+        self.ticks += 1;
 
-        self.mmu.tick(cycles);
+        let was_locked = self.cpu.locked.is_some();
+        let cycles = self.cpu.step(&mut self.mmu, &mut self.debug);
+
+        if !was_locked && self.cpu.locked.is_some() {
+            self.debug.break_execution();
+        }
+
+        // in M-cycle accurate mode part of the instruction was already
+        // stepped by its memory accesses, only catch up on the rest
+        let stepped = self.mmu.take_stepped_cycles();
+
+        // in STOP mode the system clock is halted, nothing else advances
+        if !self.cpu.interrupts.stopped {
+            self.mmu.tick(cycles.saturating_sub(stepped));
+        }
+
+        if self.mmu.ppu.frame_ready()
+            && let Some(movie) = &mut self.movie
+        {
+            movie.end_frame(&mut self.mmu.joypad, &mut self.mmu.interrupts);
+        }
+
+        self.cycles_since_flush += cycles as u64;
+        if self.cycles_since_flush >= BATTERY_FLUSH_CYCLES {
+            self.cycles_since_flush = 0;
+            // a failed write is retried on the next flush, RAM stays dirty
+            if let Some(cartridge) = &mut self.mmu.cartridge
+                && cartridge.ram_dirty
+            {
+                let _ = cartridge.flush();
+            }
+        }
+
+        if self.mmu.ppu.frame_ready()
+            && let Some(rewind) = &mut self.rewind
+            && rewind.end_frame()
+        {
+            let state = self.snapshot();
+            if let Some(rewind) = &mut self.rewind {
+                rewind.push(state, self.ticks);
+            }
+        }
 
         cycles
     }
 
+    pub fn press(&mut self, button: Button) {
+        match &mut self.movie {
+            Some(movie) if movie.mode == MovieMode::Recording => {
+                movie.set_input(movie.input() | button.state_bit());
+            }
+            // the movie is in control of the joypad
+            Some(movie) if movie.mode == MovieMode::Playing => {}
+            _ => self.mmu.joypad.press(button, &mut self.mmu.interrupts),
+        }
+    }
+
+    pub fn release(&mut self, button: Button) {
+        match &mut self.movie {
+            Some(movie) if movie.mode == MovieMode::Recording => {
+                movie.set_input(movie.input() & !button.state_bit());
+            }
+            Some(movie) if movie.mode == MovieMode::Playing => {}
+            _ => self.mmu.joypad.release(button),
+        }
+    }
+
     pub fn load_rom(&mut self, path: &str) -> Result<(), Error> {
-        self.mmu.load_rom(path)
+        // the old game's save goes out before it is replaced
+        self.flush_battery()?;
+        self.mmu.load_rom(path)?;
+        self.set_rtc_host_time(self.rtc_host_time);
+        self.rom_path = Some(path.to_string());
+        self.rom_checksum = self
+            .mmu
+            .cartridge
+            .as_ref()
+            .map_or(0, |cart| crc32(&cart.rom));
+        self.clear_rewind();
+        // pick up game.sym next to game.gb when there is one
+        let sym = Path::new(path).with_extension("sym");
+        self.debug.symbols = Symbols::load(&sym.to_string_lossy()).unwrap_or_default();
+        Ok(())
+    }
+
+    // writes battery backed cartridge RAM to <rom>.sav if it changed
+    pub fn flush_battery(&mut self) -> Result<bool, Error> {
+        match &mut self.mmu.cartridge {
+            Some(cartridge) => cartridge.flush(),
+            None => Ok(false),
+        }
+    }
+
+    // applies to the loaded cartridge and later ones, returns whether the
+    // loaded one has a clock
+    pub fn set_rtc_host_time(&mut self, on: bool) -> bool {
+        self.rtc_host_time = on;
+        let rtc = self
+            .mmu
+            .cartridge
+            .as_mut()
+            .and_then(|cartridge| cartridge.mbc.rtc());
+        match rtc {
+            Some(rtc) => {
+                rtc.set_host_time(on);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn load_symbols(&mut self, path: &str) -> Result<usize, Error> {
+        self.debug.symbols = Symbols::load(path)?;
+        Ok(self.debug.symbols.len())
     }
 
     pub fn reset(&mut self) -> Result<(), Error> {
         self.cpu.registers.reset();
+        self.cpu.locked = None;
+        self.cpu.call_stack.clear();
         Ok(())
     }
 
+    // starts over from a freshly loaded ROM, keeping the debugger and
+    // the emulation settings
+    pub fn power_cycle(&mut self) -> Result<(), Error> {
+        let Some(path) = self.rom_path.clone() else {
+            return Err(Error::new(ErrorKind::NotFound, "no ROM loaded"));
+        };
+        self.flush_battery()?;
+        let mut mmu = Self::new_mmu();
+        mmu.load_rom(&path)?;
+        mmu.m_cycle_accurate = self.mmu.m_cycle_accurate;
+        mmu.code_cache.enabled = self.mmu.code_cache.enabled;
+        mmu.doctor = self.mmu.doctor;
+
+        self.mmu = mmu;
+        self.cpu = Cpu::new();
+        if self.mmu.doctor {
+            // the state Gameboy Doctor logs start from
+            self.cpu.registers.reset();
+        }
+        self.set_rtc_host_time(self.rtc_host_time);
+        self.ticks = 0;
+        self.clear_rewind();
+        Ok(())
+    }
+
+    // movies start from power on with blank cartridge RAM
+    fn power_cycle_for_movie(&mut self) -> Result<(), Error> {
+        self.power_cycle()?;
+        if let Some(cartridge) = &mut self.mmu.cartridge {
+            cartridge.detach_save();
+        }
+        // the fresh clock follows the host again if it did before
+        self.set_rtc_host_time(self.rtc_host_time);
+        Ok(())
+    }
+
+    pub fn start_recording(&mut self, path: &str) -> Result<(), Error> {
+        self.power_cycle_for_movie()?;
+        self.movie = Some(Movie::record(self.rom_checksum));
+        self.recording_path = Some(path.to_string());
+        Ok(())
+    }
+
+    // stops recording or playback, a recording is written out
+    pub fn stop_movie(&mut self) -> Result<Option<String>, Error> {
+        let Some(movie) = self.movie.take() else {
+            return Ok(None);
+        };
+        match self.recording_path.take() {
+            Some(path) if movie.mode == MovieMode::Recording => {
+                movie.save(&path)?;
+                Ok(Some(path))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    // returns the emulator version the movie was recorded with
+    pub fn play_movie(&mut self, path: &str) -> Result<String, Error> {
+        let movie = Movie::load(path)?;
+        if movie.rom_checksum != self.rom_checksum {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "movie was recorded with a different ROM",
+            ));
+        }
+        self.power_cycle_for_movie()?;
+        let version = movie.emulator_version.clone();
+        self.movie = Some(movie);
+        self.recording_path = None;
+        Ok(version)
+    }
+
+    // in-memory state of the whole machine, see the layout at the top
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        state.bytes(STATE_MAGIC);
+        state.u16(STATE_VERSION);
+        state.u32(self.rom_checksum);
+        state.u64(self.ticks);
+        self.cpu.save_state(&mut state);
+        self.mmu.save_state(&mut state);
+        state.data
+    }
+
+    pub fn restore(&mut self, data: &[u8]) -> Result<(), Error> {
+        let mut state = StateReader::new(data);
+        let mut magic = [0; 4];
+        state.bytes(&mut magic)?;
+        if &magic != STATE_MAGIC {
+            return Err(invalid("not a save state"));
+        }
+        if state.u16()? != STATE_VERSION {
+            return Err(invalid("unsupported save state version"));
+        }
+        if state.u32()? != self.rom_checksum {
+            return Err(invalid("save state was made with a different ROM"));
+        }
+        self.ticks = state.u64()?;
+        self.cpu.load_state(&mut state)?;
+        self.mmu.load_state(&mut state)?;
+        if !state.is_empty() {
+            return Err(invalid("trailing data after save state"));
+        }
+        Ok(())
+    }
+
+    pub fn save_state(&self, path: &str) -> Result<(), Error> {
+        let mut data = self.snapshot();
+        data.extend_from_slice(&crc32(&data).to_le_bytes());
+        fs::write(path, data)
+    }
+
+    // the checksum is verified first so a damaged file can't leave the
+    // machine half restored
+    pub fn load_state(&mut self, path: &str) -> Result<(), Error> {
+        // the movie's frame count would no longer match the machine
+        if self.movie.is_some() {
+            return Err(Error::other("can't load a state while a movie is running"));
+        }
+        let data = fs::read(path)?;
+        let Some((state, checksum)) = data.split_last_chunk::<4>() else {
+            return Err(invalid("save state is truncated"));
+        };
+        if crc32(state) != u32::from_le_bytes(*checksum) {
+            return Err(invalid("save state is damaged"));
+        }
+        self.restore(state)?;
+        // captures from another timeline can't be rewound into
+        self.clear_rewind();
+        Ok(())
+    }
+
+    // "3" is slot 3 next to the ROM (game.ss3), anything else a path
+    pub fn state_path(&self, target: &str) -> String {
+        match (target.parse::<u8>(), &self.rom_path) {
+            (Ok(slot), Some(rom)) => Path::new(rom)
+                .with_extension(format!("ss{}", slot))
+                .to_string_lossy()
+                .to_string(),
+            _ => target.to_string(),
+        }
+    }
+
+    pub fn start_rewind(&mut self, interval: u32, budget: usize) {
+        self.rewind = Some(Rewind::new(interval, budget));
+    }
+
+    fn clear_rewind(&mut self) {
+        if let Some(rewind) = &mut self.rewind {
+            rewind.clear();
+        }
+    }
+
+    // goes back up to `steps` captures, returns how many it went
+    pub fn rewind(&mut self, steps: usize) -> Result<usize, Error> {
+        if self.movie.is_some() {
+            return Err(Error::other("can't rewind while a movie is running"));
+        }
+        let Some(rewind) = &mut self.rewind else {
+            return Err(Error::other("rewind is off"));
+        };
+
+        let mut state = None;
+        let mut taken = 0;
+        while taken < steps
+            && let Some(previous) = rewind.step_back()
+        {
+            state = Some(previous);
+            taken += 1;
+        }
+        if let Some(state) = state {
+            self.restore(&state)?;
+        }
+        Ok(taken)
+    }
+
     pub fn dump_history(&mut self, path: &String) -> Result<(), Error> {
         self.cpu.dump_history(path)
     }
 
+    pub fn toggle_m_cycle_accurate(&mut self) -> bool {
+        self.mmu.m_cycle_accurate = !self.mmu.m_cycle_accurate;
+        self.mmu.m_cycle_accurate
+    }
+
+    pub fn toggle_code_cache(&mut self) -> bool {
+        let cache = &mut self.mmu.code_cache;
+        cache.enabled = !cache.enabled;
+        cache.clear();
+        cache.enabled
+    }
+
+    // Traces start from power on in Gameboy Doctor's setup, so they can be
+    // diffed against its logs from the first line
+    pub fn start_trace(&mut self, path: &str) -> Result<(), Error> {
+        if self.movie.is_some() {
+            return Err(Error::other("can't start a trace while a movie is running"));
+        }
+        let trace = TraceLogger::new(path)?;
+        self.mmu.doctor = true;
+        if let Err(e) = self.power_cycle() {
+            self.mmu.doctor = false;
+            return Err(e);
+        }
+        self.debug.trace = Some(trace);
+        Ok(())
+    }
+
+    pub fn stop_trace(&mut self) -> Result<bool, Error> {
+        self.mmu.doctor = false;
+        match self.debug.trace.take() {
+            Some(mut trace) => {
+                trace.flush()?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    pub fn start_profiler(&mut self) {
+        self.debug.profiler = Some(Profiler::new(self.cpu.instruction_number));
+    }
+
+    // returns the report of the profiler that was running, if any
+    pub fn stop_profiler(&mut self) -> Option<String> {
+        let profiler = self.debug.profiler.take()?;
+        Some(profiler.report(self.cpu.instruction_number))
+    }
+
+    // innermost frame first
+    pub fn backtrace(&self) -> Vec<String> {
+        let describe = |location| match self.debug.symbols.describe(location) {
+            Some(name) => format!("{} {}", location, name),
+            None => location.to_string(),
+        };
+
+        let mut lines = vec![format!(
+            "   {}",
+            describe(Location::of(&self.mmu, self.cpu.registers.pc))
+        )];
+        for (i, frame) in self.cpu.call_stack.frames.iter().rev().enumerate() {
+            let kind = match frame.kind {
+                FrameKind::Call => "call",
+                FrameKind::Rst => "rst",
+                FrameKind::Interrupt => "irq",
+            };
+            lines.push(format!(
+                "#{:<2} {} <- ${:04X} ({})",
+                i,
+                describe(frame.function),
+                frame.caller,
+                kind
+            ));
+        }
+        if let Some(warning) = &self.cpu.call_stack.warning {
+            lines.push(format!("! {}", warning));
+        }
+        lines
+    }
+
     pub fn toggle_log(&mut self) {
         self.debug.log_cpu = !self.debug.log_cpu;
     }
 
+    pub fn toggle_break_on_trap(&mut self) -> bool {
+        self.debug.break_on_trap = !self.debug.break_on_trap;
+        self.debug.break_on_trap
+    }
+
     pub fn add_breakpoint(&mut self, address: u16) -> String {
         self.debug.add_breakpoint(address)
     }
@@ -84,7 +505,23 @@ impl Emulator {
         false
     }
 
+    pub fn take_break_request(&mut self) -> bool {
+        self.debug.take_break_request()
+    }
+
+    // the rumble motor was switched on or off since the last call
+    pub fn take_rumble(&mut self) -> Option<bool> {
+        self.mmu.cartridge.as_mut()?.mbc.take_rumble()
+    }
+
     pub fn draw_call(&self) -> bool {
-        self.mmu.ppu.frame_ready()
+        // no frames are produced while stopped, keep the frontend responsive
+        self.mmu.ppu.frame_ready() || self.cpu.interrupts.stopped
+    }
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Self::new()
     }
 }
@@ -1,3 +1,7 @@
+use std::io::Error;
+
+use crate::state::{SaveState, StateReader, StateWriter};
+
 #[derive(Copy, Clone, Debug)]
 pub enum Interrupt {
     VBlank,
@@ -72,3 +76,22 @@ impl InterruptController {
         self.iflag & self.ie & 0x1F
     }
 }
+
+impl Default for InterruptController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SaveState for InterruptController {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.iflag);
+        state.u8(self.ie);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Error> {
+        self.iflag = state.u8()?;
+        self.ie = state.u8()?;
+        Ok(())
+    }
+}
@@ -1,13 +1,218 @@
-pub struct Joypad {}
+use std::io::Error;
+
+use crate::interrupt_controller::{Interrupt, InterruptController};
+use crate::state::{SaveState, StateReader, StateWriter};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl Button {
+    pub const ALL: [Button; 8] = [
+        Button::Right,
+        Button::Left,
+        Button::Up,
+        Button::Down,
+        Button::A,
+        Button::B,
+        Button::Select,
+        Button::Start,
+    ];
+
+    // bit of the button in `Joypad::state`
+    pub fn state_bit(self) -> u8 {
+        let index = Button::ALL.iter().position(|b| *b == self).unwrap();
+        1 << index
+    }
+
+    // line of the button inside its group
+    fn bit(self) -> u8 {
+        match self {
+            Button::Right | Button::A => 1 << 0,
+            Button::Left | Button::B => 1 << 1,
+            Button::Up | Button::Select => 1 << 2,
+            Button::Down | Button::Start => 1 << 3,
+        }
+    }
+
+    fn is_direction(self) -> bool {
+        matches!(
+            self,
+            Button::Right | Button::Left | Button::Up | Button::Down
+        )
+    }
+}
+
+// P1/JOYP (FF00)
+//
+// bit 5   select action buttons (0 = selected)
+// bit 4   select direction buttons (0 = selected)
+// bit 3-0 input lines, 0 = a pressed button in a selected group
+//
+// bits 7-6 are unused and read as 1
+pub struct Joypad {
+    select: u8,
+    // pressed buttons, one bit per line
+    directions: u8,
+    actions: u8,
+}
 
 impl Joypad {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            select: 0x00, // P1 reads $CF after the boot ROM
+            directions: 0,
+            actions: 0,
+        }
     }
 
     pub fn read_reg(&self) -> u8 {
-        0
+        0xC0 | self.select | self.lines()
+    }
+
+    pub fn write_reg(&mut self, value: u8, interrupts: &mut InterruptController) {
+        // selecting a group with a held button pulls its line low as well
+        self.update(interrupts, |joypad| joypad.select = value & 0x30);
+    }
+
+    pub fn press(&mut self, button: Button, interrupts: &mut InterruptController) {
+        self.update(interrupts, |joypad| *joypad.group(button) |= button.bit());
+    }
+
+    pub fn release(&mut self, button: Button) {
+        *self.group(button) &= !button.bit();
+    }
+
+    pub fn is_pressed(&self, button: Button) -> bool {
+        let group = if button.is_direction() {
+            self.directions
+        } else {
+            self.actions
+        };
+        group & button.bit() != 0
+    }
+
+    // all eight buttons, one bit each in the order of `Button::ALL`
+    pub fn state(&self) -> u8 {
+        Button::ALL
+            .iter()
+            .filter(|button| self.is_pressed(**button))
+            .fold(0, |state, button| state | button.state_bit())
+    }
+
+    pub fn set_state(&mut self, state: u8, interrupts: &mut InterruptController) {
+        for button in Button::ALL {
+            if state & button.state_bit() != 0 {
+                self.press(button, interrupts);
+            } else {
+                self.release(button);
+            }
+        }
     }
 
-    pub fn write_reg(&mut self, _value: u8) {}
+    // low nibble of P1
+    pub fn lines(&self) -> u8 {
+        let mut pressed = 0;
+        if self.select & 0x10 == 0 {
+            pressed |= self.directions;
+        }
+        if self.select & 0x20 == 0 {
+            pressed |= self.actions;
+        }
+        !pressed & 0x0F
+    }
+
+    fn group(&mut self, button: Button) -> &mut u8 {
+        if button.is_direction() {
+            &mut self.directions
+        } else {
+            &mut self.actions
+        }
+    }
+
+    // the interrupt fires when any input line goes from high to low
+    fn update(&mut self, interrupts: &mut InterruptController, change: impl FnOnce(&mut Self)) {
+        let before = self.lines();
+        change(self);
+        if before & !self.lines() != 0 {
+            interrupts.request(Interrupt::Joypad);
+        }
+    }
+}
+
+impl Default for Joypad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SaveState for Joypad {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.select);
+        state.u8(self.directions);
+        state.u8(self.actions);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Error> {
+        self.select = state.u8()? & 0x30;
+        self.directions = state.u8()? & 0x0F;
+        self.actions = state.u8()? & 0x0F;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn select_bits_pick_the_button_group() {
+        let mut interrupts = InterruptController::new();
+        let mut joypad = Joypad::new();
+        joypad.press(Button::Start, &mut interrupts);
+        joypad.press(Button::Left, &mut interrupts);
+
+        joypad.write_reg(0x10, &mut interrupts); // actions
+        assert_eq!(joypad.read_reg(), 0xD7);
+        joypad.write_reg(0x20, &mut interrupts); // directions
+        assert_eq!(joypad.read_reg(), 0xED);
+        joypad.write_reg(0x30, &mut interrupts); // nothing
+        assert_eq!(joypad.read_reg(), 0xFF);
+
+        joypad.release(Button::Left);
+        joypad.write_reg(0x20, &mut interrupts);
+        assert_eq!(joypad.read_reg(), 0xEF);
+    }
+
+    #[test]
+    fn interrupt_on_high_to_low_transitions() {
+        let mut interrupts = InterruptController::new();
+        interrupts.iflag = 0xE0;
+        let mut joypad = Joypad::new();
+        joypad.write_reg(0x20, &mut interrupts); // directions
+
+        // not selected, the line stays high
+        joypad.press(Button::A, &mut interrupts);
+        assert_eq!(interrupts.iflag, 0xE0);
+
+        joypad.press(Button::Up, &mut interrupts);
+        assert_eq!(interrupts.iflag, 0xE0 | Interrupt::Joypad.bit());
+
+        // releasing is a low-to-high transition
+        interrupts.iflag = 0xE0;
+        joypad.release(Button::Up);
+        assert_eq!(interrupts.iflag, 0xE0);
+
+        // selecting the group of a held button pulls its line low
+        joypad.write_reg(0x10, &mut interrupts);
+        assert_eq!(interrupts.iflag, 0xE0 | Interrupt::Joypad.bit());
+    }
 }
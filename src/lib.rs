@@ -0,0 +1,15 @@
+pub mod app;
+pub mod apu;
+pub mod cpu;
+pub mod debug;
+pub mod emulator;
+pub mod interrupt_controller;
+pub mod joypad;
+pub mod mmu;
+pub mod movie;
+pub mod ppu;
+pub mod rewind;
+pub mod serial;
+pub mod state;
+pub mod timer;
+pub mod utils;
@@ -1,16 +1,4 @@
-mod app;
-mod apu;
-mod cpu;
-mod debug;
-mod emulator;
-mod interrupt_controller;
-mod joypad;
-mod mmu;
-mod ppu;
-mod serial;
-mod timer;
-mod utils;
-
+use gameboy_rs::app;
 use std::panic;
 
 fn print_panic(err: Box<dyn std::any::Any + Send>) {
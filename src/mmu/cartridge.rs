@@ -1,10 +1,18 @@
 use crate::mmu::mbc::{Mbc, Mbc1, Mbc2, Mbc3, Mbc5, Mbcs, NoMbc};
-use std::io::Error;
+use crate::mmu::rtc::{Rtc, TRAILER_SIZE};
+use crate::state::{SaveState, StateReader, StateWriter, invalid};
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
 
 pub struct Cartridge {
     pub rom: Vec<u8>,
     pub ram: Vec<u8>,
     pub mbc: Box<dyn Mbc>,
+    pub cgb: bool,
+    // where battery backed RAM is kept, <rom>.sav
+    pub save_path: Option<PathBuf>,
+    // RAM was written since it was last saved
+    pub ram_dirty: bool,
 }
 
 fn detect_mbc(rom: &[u8]) -> Result<Mbcs, Error> {
@@ -21,19 +29,66 @@ fn detect_mbc(rom: &[u8]) -> Result<Mbcs, Error> {
     }
 }
 
+// the logo every header has to carry at 0104-0133
+const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+// MBC1M multicarts are 1 MiB of four 256 KiB games, each starting with its
+// own header. Their cartridge header doesn't tell them apart from plain MBC1,
+// the repeated logos do.
+fn is_multicart(rom: &[u8]) -> bool {
+    if rom.len() != 0x10_0000 {
+        return false;
+    }
+    let logos = (0..4)
+        .filter(|game| {
+            let header = game * 0x4_0000 + 0x104;
+            rom[header..header + NINTENDO_LOGO.len()] == NINTENDO_LOGO
+        })
+        .count();
+    // the menu and at least one game
+    logos > 1
+}
+
+fn has_rtc(rom: &[u8]) -> bool {
+    matches!(rom[0x147], 0x0F | 0x10)
+}
+
+fn has_rumble(rom: &[u8]) -> bool {
+    matches!(rom[0x147], 0x1C..=0x1E)
+}
+
+fn has_battery(rom: &[u8]) -> bool {
+    matches!(
+        rom[0x147],
+        0x03 | 0x06 | 0x09 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFF
+    )
+}
+
+// in bytes
 fn detect_ram_size(rom: &[u8]) -> Result<usize, Error> {
-    match rom[0x149] {
-        0x00 => Ok(0),
-        0x01 => Ok(2),
-        0x02 => Ok(8),
-        0x03 => Ok(32),
-        0x04 => Ok(128),
-        0x05 => Ok(64),
-        _ => Err(Error::new(
-            std::io::ErrorKind::InvalidData,
-            "RAM size invalid!",
-        )),
+    // MBC2 has 512 half bytes built in, the header declares none
+    if let Ok(Mbcs::Mbc2) = detect_mbc(rom) {
+        return Ok(512);
     }
+    let kib = match rom[0x149] {
+        0x00 => 0,
+        0x01 => 2,
+        0x02 => 8,
+        0x03 => 32,
+        0x04 => 128,
+        0x05 => 64,
+        _ => {
+            return Err(Error::new(
+                std::io::ErrorKind::InvalidData,
+                "RAM size invalid!",
+            ));
+        }
+    };
+    Ok(kib * 1024)
 }
 
 impl Cartridge {
@@ -46,16 +101,99 @@ impl Cartridge {
             ));
         }
 
-        let mbc: Box<dyn Mbc> = match detect_mbc(&rom)? {
+        let mut mbc: Box<dyn Mbc> = match detect_mbc(&rom)? {
             Mbcs::NoMbc => Box::new(NoMbc::new()),
-            Mbcs::Mbc1 => Box::new(Mbc1::new()),
+            Mbcs::Mbc1 => Box::new(Mbc1::new(rom.len(), is_multicart(&rom))),
             Mbcs::Mbc2 => Box::new(Mbc2::new()),
-            Mbcs::Mbc3 => Box::new(Mbc3::new()),
-            Mbcs::Mbc5 => Box::new(Mbc5::new()),
+            Mbcs::Mbc3 => Box::new(Mbc3::new(has_rtc(&rom))),
+            Mbcs::Mbc5 => Box::new(Mbc5::new(has_rumble(&rom))),
         };
-        let ram = vec![0u8; detect_ram_size(&rom)?];
+        let mut ram = vec![0u8; detect_ram_size(&rom)?];
+        let cgb = rom[0x143] & 0x80 != 0;
+
+        let save_path = has_battery(&rom).then(|| Path::new(path).with_extension("sav"));
+        if let Some(save_path) = &save_path {
+            load_save(save_path, &mut ram, mbc.as_mut())?;
+        }
+
+        Ok(Self {
+            rom,
+            ram,
+            mbc,
+            cgb,
+            save_path,
+            ram_dirty: false,
+        })
+    }
 
-        Ok(Self { rom, ram, mbc })
+    // Back to a cartridge without a save, nothing is written out from here
+    // on. Movies use it so that playback doesn't depend on the .sav on disk
+    // and doesn't overwrite it.
+    pub fn detach_save(&mut self) {
+        self.ram.fill(0);
+        if let Some(rtc) = self.mbc.rtc() {
+            *rtc = Rtc::new();
+        }
+        self.save_path = None;
+        self.ram_dirty = false;
+    }
+
+    // Writes battery backed RAM out if it changed, returns whether it did.
+    // A running clock changes all the time, with one it is always written.
+    pub fn flush(&mut self) -> Result<bool, Error> {
+        let Some(save_path) = &self.save_path else {
+            return Ok(false);
+        };
+        let mut data = self.ram.clone();
+        match self.mbc.rtc() {
+            Some(rtc) => data.extend_from_slice(&rtc.trailer()),
+            None if !self.ram_dirty => return Ok(false),
+            None => {}
+        }
+        // written next to it and renamed over, so a crash mid-write can't
+        // leave a truncated save behind
+        let temp_path = save_path.with_extension("sav.tmp");
+        std::fs::write(&temp_path, data)?;
+        std::fs::rename(&temp_path, save_path)?;
+        self.ram_dirty = false;
+        Ok(true)
+    }
+}
+
+// Raw RAM dump like every other emulator writes, followed by the clock
+// for cartridges with one. Shorter files only fill the start of RAM.
+fn load_save(path: &Path, ram: &mut [u8], mbc: &mut dyn Mbc) -> Result<(), Error> {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let length = data.len().min(ram.len());
+    ram[..length].copy_from_slice(&data[..length]);
+
+    if let Some(rtc) = mbc.rtc()
+        && let Some(trailer) = data[length..].first_chunk::<TRAILER_SIZE>()
+    {
+        rtc.load_trailer(trailer);
+    }
+    Ok(())
+}
+
+// the ROM itself is not part of a save state, only what the game can change
+impl SaveState for Cartridge {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.block(&self.ram);
+        self.mbc.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Error> {
+        let ram = state.block()?;
+        if ram.len() != self.ram.len() {
+            return Err(invalid("cartridge RAM size does not match"));
+        }
+        self.ram.copy_from_slice(ram);
+        self.ram_dirty = true;
+        self.mbc.load_state(state)
     }
 }
 
@@ -102,21 +240,106 @@ mod tests {
         assert_eq!(detect_ram_size(&rom).unwrap(), 0);
 
         rom[0x149] = 0x01;
-        assert_eq!(detect_ram_size(&rom).unwrap(), 2);
+        assert_eq!(detect_ram_size(&rom).unwrap(), 2 * 1024);
 
         rom[0x149] = 0x02;
-        assert_eq!(detect_ram_size(&rom).unwrap(), 8);
+        assert_eq!(detect_ram_size(&rom).unwrap(), 8 * 1024);
 
         rom[0x149] = 0x03;
-        assert_eq!(detect_ram_size(&rom).unwrap(), 32);
+        assert_eq!(detect_ram_size(&rom).unwrap(), 32 * 1024);
 
         rom[0x149] = 0x04;
-        assert_eq!(detect_ram_size(&rom).unwrap(), 128);
+        assert_eq!(detect_ram_size(&rom).unwrap(), 128 * 1024);
 
         rom[0x149] = 0x05;
-        assert_eq!(detect_ram_size(&rom).unwrap(), 64);
+        assert_eq!(detect_ram_size(&rom).unwrap(), 64 * 1024);
 
         rom[0x149] = 0xFF;
         assert!(detect_ram_size(&rom).is_err());
+
+        // whatever MBC2 headers declare
+        rom[0x147] = 0x06;
+        assert_eq!(detect_ram_size(&rom).unwrap(), 512);
+    }
+
+    #[test]
+    fn multicarts_are_told_apart_by_their_logos() {
+        let mut rom = vec![0; 0x10_0000];
+        rom[0x104..0x134].copy_from_slice(&NINTENDO_LOGO);
+        assert!(!is_multicart(&rom));
+        rom[0x4_0104..0x4_0134].copy_from_slice(&NINTENDO_LOGO);
+        assert!(is_multicart(&rom));
+
+        // the same logos in a 2 MiB ROM are just data
+        rom.resize(0x20_0000, 0);
+        assert!(!is_multicart(&rom));
+    }
+
+    #[test]
+    fn test_has_battery() {
+        let mut rom = vec![0; 0x150];
+        for (code, battery) in [(0x01, false), (0x03, true), (0x06, true), (0x13, true)] {
+            rom[0x147] = code;
+            assert_eq!(has_battery(&rom), battery);
+        }
+    }
+
+    #[test]
+    fn battery_ram_is_loaded_and_flushed_as_raw_sav() {
+        let dir = std::env::temp_dir().join(format!("sav-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let rom_path = dir.join("game.gb");
+        let sav_path = dir.join("game.sav");
+
+        // MBC1+RAM+BATTERY with 8 KiB of RAM
+        let mut rom = vec![0; 0x8000];
+        rom[0x147] = 0x03;
+        rom[0x149] = 0x02;
+        std::fs::write(&rom_path, &rom).unwrap();
+        std::fs::write(&sav_path, [1, 2, 3]).unwrap();
+
+        let mut cartridge = Cartridge::new(rom_path.to_str().unwrap()).unwrap();
+        assert_eq!(cartridge.ram.len(), 8 * 1024);
+        assert_eq!(cartridge.ram[..4], [1, 2, 3, 0]);
+        assert!(!cartridge.flush().unwrap());
+
+        cartridge.ram[0x1FFF] = 0x42;
+        cartridge.ram_dirty = true;
+        assert!(cartridge.flush().unwrap());
+        let sav = std::fs::read(&sav_path).unwrap();
+        let temp_left = dir.join("game.sav.tmp").exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(!temp_left);
+        assert_eq!(sav.len(), 8 * 1024);
+        assert_eq!(sav[0x1FFF], 0x42);
+    }
+
+    #[test]
+    fn clock_is_kept_in_the_sav_trailer() {
+        let dir = std::env::temp_dir().join(format!("rtc-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let rom_path = dir.join("game.gb");
+        let sav_path = dir.join("game.sav");
+
+        // MBC3+TIMER+RAM+BATTERY with 32 KiB of RAM
+        let mut rom = vec![0; 0x8000];
+        rom[0x147] = 0x10;
+        rom[0x149] = 0x03;
+        std::fs::write(&rom_path, &rom).unwrap();
+        let mut sav = vec![0; 32 * 1024 + TRAILER_SIZE];
+        sav[32 * 1024] = 7; // seconds
+        std::fs::write(&sav_path, &sav).unwrap();
+
+        let mut cartridge = Cartridge::new(rom_path.to_str().unwrap()).unwrap();
+        let rtc = cartridge.mbc.rtc().unwrap();
+        rtc.latch();
+        assert_eq!(rtc.read(0x08), 7);
+
+        // written even without RAM changes, the clock keeps running
+        assert!(cartridge.flush().unwrap());
+        let sav = std::fs::read(&sav_path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(sav.len(), 32 * 1024 + TRAILER_SIZE);
+        assert_eq!(sav[32 * 1024], 7);
     }
 }
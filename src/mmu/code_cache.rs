@@ -0,0 +1,95 @@
+use crate::cpu::decoder::{DecodedInstruction, decode};
+
+const BANK_SIZE: usize = 0x4000;
+
+type Slots = Vec<Option<DecodedInstruction>>;
+
+// Decoded instructions for the regions code runs from. ROM entries are keyed
+// by bank and address, so a bank switch only selects another set of entries
+// and nothing has to be thrown away. RAM entries are dropped when one of
+// their bytes is written.
+pub struct CodeCache {
+    pub enabled: bool,
+    // allocated per bank on first use, large ROMs mostly run from a few banks
+    rom: Vec<Slots>,
+    wram: Slots,
+    hram: Slots,
+}
+
+impl CodeCache {
+    pub fn new() -> Self {
+        Self {
+            enabled: true,
+            rom: Vec::new(),
+            wram: vec![None; 0x2000],
+            hram: vec![None; 0x7F],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.rom.clear();
+        self.wram.fill(None);
+        self.hram.fill(None);
+    }
+
+    pub fn get(&mut self, bank: usize, addr: u16) -> Option<DecodedInstruction> {
+        *self.slot(bank, addr)?
+    }
+
+    // decodes the opcode and operand bytes at addr, `None` when addr is
+    // outside the cached regions
+    pub fn insert(&mut self, bank: usize, addr: u16, bytes: [u8; 3]) -> Option<DecodedInstruction> {
+        let slot = self.slot(bank, addr)?;
+        let [opcode, lo, hi] = bytes;
+        *slot = Some(DecodedInstruction {
+            entry: decode(opcode),
+            operands: [lo, hi],
+        });
+        *slot
+    }
+
+    // a write can change the opcode or an operand of instructions starting
+    // up to two bytes before it
+    pub fn invalidate(&mut self, addr: u16) {
+        for start in addr.saturating_sub(2)..=addr {
+            if let Some(slot) = self.ram_slot(start) {
+                *slot = None;
+            }
+        }
+    }
+
+    fn slot(&mut self, bank: usize, addr: u16) -> Option<&mut Option<DecodedInstruction>> {
+        match addr {
+            0x0000..=0x7FFF => {
+                // instructions running into the next region are not cached
+                let offset = addr as usize % BANK_SIZE;
+                if offset > BANK_SIZE - 3 {
+                    return None;
+                }
+                if self.rom.len() <= bank {
+                    self.rom.resize(bank + 1, Vec::new());
+                }
+                let slots = &mut self.rom[bank];
+                if slots.is_empty() {
+                    *slots = vec![None; BANK_SIZE];
+                }
+                Some(&mut slots[offset])
+            }
+            _ => self.ram_slot(addr),
+        }
+    }
+
+    fn ram_slot(&mut self, addr: u16) -> Option<&mut Option<DecodedInstruction>> {
+        match addr {
+            0xC000..=0xDFFD => Some(&mut self.wram[(addr - 0xC000) as usize]),
+            0xFF80..=0xFFFC => Some(&mut self.hram[(addr - 0xFF80) as usize]),
+            _ => None,
+        }
+    }
+}
+
+impl Default for CodeCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
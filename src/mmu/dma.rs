@@ -0,0 +1,81 @@
+// OAM DMA: a write to FF46 copies 160 bytes from XX00–XX9F into OAM,
+// one byte per M-cycle.
+
+use std::io::Error;
+
+use crate::state::{SaveState, StateReader, StateWriter};
+
+const OAM_SIZE: u16 = 0xA0;
+
+pub struct Dma {
+    pub source: u8, // last value written to FF46
+    index: u16,
+    active: bool,
+    cycles: u32,
+}
+
+impl Dma {
+    pub fn new() -> Self {
+        Self {
+            source: 0xFF,
+            index: 0,
+            active: false,
+            cycles: 0,
+        }
+    }
+
+    pub fn start(&mut self, value: u8) {
+        self.source = value;
+        self.index = 0;
+        self.active = true;
+        self.cycles = 0;
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        if self.active {
+            self.cycles += cycles;
+        }
+    }
+
+    // returns the next (source address, OAM offset) pair once a full M-cycle has elapsed
+    pub fn next_transfer(&mut self) -> Option<(u16, usize)> {
+        if !self.active || self.cycles < 4 {
+            return None;
+        }
+        self.cycles -= 4;
+
+        let src = ((self.source as u16) << 8) | self.index;
+        let dst = self.index as usize;
+
+        self.index += 1;
+        if self.index == OAM_SIZE {
+            self.active = false;
+            self.cycles = 0;
+        }
+
+        Some((src, dst))
+    }
+}
+
+impl Default for Dma {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SaveState for Dma {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.source);
+        state.u16(self.index);
+        state.bool(self.active);
+        state.u32(self.cycles);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Error> {
+        self.source = state.u8()?;
+        self.index = state.u16()?;
+        self.active = state.bool()?;
+        self.cycles = state.u32()?;
+        Ok(())
+    }
+}
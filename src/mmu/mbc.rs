@@ -1,3 +1,8 @@
+use std::io::Error;
+
+use crate::mmu::rtc::Rtc;
+use crate::state::{StateReader, StateWriter};
+
 pub enum Mbcs {
     NoMbc,
     Mbc1,
@@ -13,7 +18,30 @@ pub trait Mbc {
     fn read_ram(&self, ram: &[u8], addr: u16) -> u8;
     fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8);
 
+    // bank currently mapped at a 0000-7FFF address
+    fn rom_bank(&self, addr: u16) -> usize;
+
     fn name(&self) -> &str;
+
+    // `cycles` at the normal clock rate, for mappers with their own clock
+    fn tick(&mut self, _cycles: u32) {}
+
+    fn rtc(&mut self) -> Option<&mut Rtc> {
+        None
+    }
+
+    // the new motor state when it changed since the last call, for
+    // cartridges with one
+    fn take_rumble(&mut self) -> Option<bool> {
+        None
+    }
+
+    // banking registers for save states, mappers without any keep the defaults
+    fn save_state(&self, _state: &mut StateWriter) {}
+
+    fn load_state(&mut self, _state: &mut StateReader) -> Result<(), Error> {
+        Ok(())
+    }
 }
 ////////////////////////////////////////////////////////////////////////////////
 pub struct NoMbc;
@@ -22,6 +50,11 @@ impl NoMbc {
         Self {}
     }
 }
+impl Default for NoMbc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl Mbc for NoMbc {
     fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
         match addr {
@@ -55,58 +88,88 @@ impl Mbc for NoMbc {
         }
     }
 
+    fn rom_bank(&self, addr: u16) -> usize {
+        addr as usize / 0x4000
+    }
+
     fn name(&self) -> &str {
         "NoMbc"
     }
 }
 ////////////////////////////////////////////////////////////////////////////////
 
+// MBC1, up to 2 MiB of ROM and 32 KiB of RAM
+//
+// 0000-1FFF  RAM enable, 0x0A enables
+// 2000-3FFF  ROM bank, lower 5 bits, 0 selects 1
+// 4000-5FFF  2 more bits, ROM bank bits 5-6 or the RAM bank
+// 6000-7FFF  mode, in mode 1 the 2 bits also bank 0000-3FFF and RAM
+//
+// Bank numbers past the end of the ROM or RAM wrap around. MBC1M
+// multicarts only wire 4 bits of the lower register, the 2 bits above
+// pick one of the four 256 KiB games.
 pub struct Mbc1 {
     ram_enabled: bool,
     rom_bank_low: u8,
     rom_bank_high: u8,
     mode: u8,
+    multicart: bool,
+    // power of two, for masking bank numbers
+    rom_banks: usize,
 }
 impl Mbc1 {
-    pub fn new() -> Self {
+    pub fn new(rom_size: usize, multicart: bool) -> Self {
         Self {
             ram_enabled: false,
             rom_bank_low: 1, // Bank 1 is default
             rom_bank_high: 0,
             mode: 0,
+            multicart,
+            rom_banks: rom_size.div_ceil(0x4000).next_power_of_two().max(2),
         }
     }
-    fn get_rom_bank(&self) -> u8 {
-        let mut bank = match self.mode {
-            0 => self.rom_bank_low | (self.rom_bank_high << 5),
-            1 => self.rom_bank_low,
-            _ => unreachable!(),
-        };
 
-        if bank & 0x1F == 0 {
-            bank |= 1;
+    // the 2 upper bits in place
+    fn high_bits(&self) -> usize {
+        let shift = if self.multicart { 4 } else { 5 };
+        (self.rom_bank_high as usize) << shift
+    }
+
+    // mapped at 0000-3FFF
+    fn zero_bank(&self) -> usize {
+        match self.mode {
+            0 => 0,
+            _ => self.high_bits() & (self.rom_banks - 1),
         }
-        bank
+    }
+
+    // mapped at 4000-7FFF
+    fn get_rom_bank(&self) -> usize {
+        let low_mask = if self.multicart { 0x0F } else { 0x1F };
+        let bank = self.high_bits() | (self.rom_bank_low & low_mask) as usize;
+        bank & (self.rom_banks - 1)
+    }
+
+    fn ram_offset(&self, ram: &[u8], addr: u16) -> usize {
+        let bank = match self.mode {
+            0 => 0,
+            _ => self.rom_bank_high as usize,
+        };
+        // smaller RAM ignores the upper bank bits and mirrors
+        (bank * 0x2000 + (addr as usize - 0xA000)) % ram.len().max(1)
     }
 }
 impl Mbc for Mbc1 {
     fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
-        match addr {
-            0x0000..=0x3FFF => rom[addr as usize],
-            0x4000..=0x7FFF => {
-                let bank = self.get_rom_bank();
-                let offset = (bank as usize * 0x4000) + (addr as usize - 0x4000);
-                rom.get(offset).copied().unwrap_or(0xFF)
-            }
-            _ => 0xFF,
-        }
+        let offset = self.rom_bank(addr) * 0x4000 + (addr as usize & 0x3FFF);
+        rom.get(offset).copied().unwrap_or(0xFF)
     }
 
     fn write_rom(&mut self, _rom: &mut [u8], addr: u16, value: u8) {
         match addr {
             0x0000..=0x1FFF => self.ram_enabled = (value & 0x0F) == 0x0A, // 0000–1FFF : RAM enable
             0x2000..=0x3FFF => {
-                // 2000–3FFF : ROM bank low 5 bits
+                // 2000–3FFF : ROM bank low 5 bits, the zero check sees all of them
                 self.rom_bank_low = value & 0x1F;
                 if self.rom_bank_low == 0 {
                     self.rom_bank_low = 1;
@@ -122,113 +185,607 @@ impl Mbc for Mbc1 {
         if !self.ram_enabled {
             return 0xFF;
         }
-
-        let bank = match self.mode {
-            0 => 0,
-            1 => self.rom_bank_high,
-            _ => 0,
-        };
-
-        let offset = (bank as usize * 0x2000) + (addr as usize - 0xA000);
-
-        ram.get(offset).copied().unwrap_or(0xFF)
+        ram.get(self.ram_offset(ram, addr)).copied().unwrap_or(0xFF)
     }
 
     fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) {
         if !self.ram_enabled {
             return;
         }
-
-        let bank = match self.mode {
-            0 => 0,
-            1 => self.rom_bank_high,
-            _ => 0,
-        };
-
-        let offset = (bank as usize * 0x2000) + (addr as usize - 0xA000);
-
+        let offset = self.ram_offset(ram, addr);
         if let Some(byte) = ram.get_mut(offset) {
             *byte = value;
         }
     }
 
+    fn rom_bank(&self, addr: u16) -> usize {
+        match addr {
+            0x0000..=0x3FFF => self.zero_bank(),
+            _ => self.get_rom_bank(),
+        }
+    }
+
     fn name(&self) -> &str {
         "Mbc1"
     }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.ram_enabled);
+        state.u8(self.rom_bank_low);
+        state.u8(self.rom_bank_high);
+        state.u8(self.mode);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Error> {
+        self.ram_enabled = state.bool()?;
+        self.rom_bank_low = state.u8()? & 0x1F;
+        self.rom_bank_high = state.u8()? & 0x03;
+        self.mode = state.u8()? & 0x01;
+        Ok(())
+    }
 }
 ////////////////////////////////////////////////////////////////////////////////
-pub struct Mbc2;
+// MBC2, up to 256 KiB of ROM and 512 half bytes of RAM built in
+//
+// 0000-3FFF  with address bit 8 clear RAM enable, 0x0A enables, with it
+//            set the ROM bank, 4 bits, 0 selects 1
+// A000-BFFF  the RAM, repeating every 512 bytes, upper nibbles read as 1
+pub struct Mbc2 {
+    ram_enabled: bool,
+    rom_bank: u8,
+}
 impl Mbc2 {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            ram_enabled: false,
+            rom_bank: 1,
+        }
+    }
+}
+impl Default for Mbc2 {
+    fn default() -> Self {
+        Self::new()
     }
 }
 impl Mbc for Mbc2 {
     fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
-        // If rom is empty, return 0xFF
-        rom.get(addr as usize).copied().unwrap_or(0xFF)
+        let offset = match addr {
+            0x0000..=0x3FFF => addr as usize,
+            _ => self.rom_bank as usize * 0x4000 + (addr as usize - 0x4000),
+        };
+        // banks past the end of the ROM mirror the ones before
+        rom.get(offset % rom.len().max(1)).copied().unwrap_or(0xFF)
     }
 
-    fn write_rom(&mut self, _: &mut [u8], _: u16, _: u8) {}
+    fn write_rom(&mut self, _: &mut [u8], addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x3FFF if addr & 0x0100 == 0 => self.ram_enabled = value & 0x0F == 0x0A,
+            0x0000..=0x3FFF => self.rom_bank = (value & 0x0F).max(1),
+            _ => {}
+        }
+    }
 
     fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
-        ram.get(addr as usize).copied().unwrap_or(0xFF)
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+        ram.get(addr as usize & 0x01FF)
+            .map_or(0xFF, |nibble| nibble | 0xF0)
     }
 
-    fn write_ram(&mut self, _: &mut [u8], _: u16, _: u8) {}
+    fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) {
+        if !self.ram_enabled {
+            return;
+        }
+        if let Some(nibble) = ram.get_mut(addr as usize & 0x01FF) {
+            *nibble = value & 0x0F;
+        }
+    }
+
+    fn rom_bank(&self, addr: u16) -> usize {
+        match addr {
+            0x0000..=0x3FFF => 0,
+            _ => self.rom_bank as usize,
+        }
+    }
 
     fn name(&self) -> &str {
         "Mbc2"
     }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.ram_enabled);
+        state.u8(self.rom_bank);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Error> {
+        self.ram_enabled = state.bool()?;
+        self.rom_bank = (state.u8()? & 0x0F).max(1);
+        Ok(())
+    }
 }
 ////////////////////////////////////////////////////////////////////////////////
-pub struct Mbc3;
+// MBC3, up to 2 MiB of ROM, 32 KiB of RAM and an optional real time clock
+//
+// 0000-1FFF  RAM and clock enable, 0x0A enables
+// 2000-3FFF  ROM bank, 7 bits, 0 selects 1
+// 4000-5FFF  RAM bank 0-3, or clock register 0x08-0x0C
+// 6000-7FFF  writing 0 then 1 latches the clock
+pub struct Mbc3 {
+    ram_enabled: bool,
+    rom_bank: u8,
+    ram_select: u8,
+    latch: u8,
+    rtc: Option<Rtc>,
+}
 impl Mbc3 {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(has_rtc: bool) -> Self {
+        Self {
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_select: 0,
+            latch: 0xFF,
+            rtc: has_rtc.then(Rtc::new),
+        }
+    }
+
+    fn ram_offset(&self, addr: u16) -> usize {
+        (self.ram_select as usize & 0x03) * 0x2000 + (addr as usize - 0xA000)
     }
 }
 impl Mbc for Mbc3 {
     fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
-        // If rom is empty, return 0xFF
-        rom.get(addr as usize).copied().unwrap_or(0xFF)
+        let offset = match addr {
+            0x0000..=0x3FFF => addr as usize,
+            _ => self.rom_bank as usize * 0x4000 + (addr as usize - 0x4000),
+        };
+        // banks past the end of the ROM mirror the ones before
+        rom.get(offset % rom.len().max(1)).copied().unwrap_or(0xFF)
     }
 
-    fn write_rom(&mut self, _: &mut [u8], _: u16, _: u8) {}
+    fn write_rom(&mut self, _: &mut [u8], addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = (value & 0x7F).max(1),
+            0x4000..=0x5FFF => self.ram_select = value,
+            0x6000..=0x7FFF => {
+                if self.latch == 0x00
+                    && value == 0x01
+                    && let Some(rtc) = &mut self.rtc
+                {
+                    rtc.latch();
+                }
+                self.latch = value;
+            }
+            _ => {}
+        }
+    }
 
     fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
-        ram.get(addr as usize).copied().unwrap_or(0xFF)
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+        match (self.ram_select, &self.rtc) {
+            (0x00..=0x03, _) => ram.get(self.ram_offset(addr)).copied().unwrap_or(0xFF),
+            (0x08..=0x0C, Some(rtc)) => rtc.read(self.ram_select),
+            _ => 0xFF,
+        }
     }
 
-    fn write_ram(&mut self, _: &mut [u8], _: u16, _: u8) {}
+    fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) {
+        if !self.ram_enabled {
+            return;
+        }
+        let offset = self.ram_offset(addr);
+        match (self.ram_select, &mut self.rtc) {
+            (0x00..=0x03, _) => {
+                if let Some(byte) = ram.get_mut(offset) {
+                    *byte = value;
+                }
+            }
+            (0x08..=0x0C, Some(rtc)) => rtc.write(self.ram_select, value),
+            _ => {}
+        }
+    }
+
+    fn rom_bank(&self, addr: u16) -> usize {
+        match addr {
+            0x0000..=0x3FFF => 0,
+            _ => self.rom_bank as usize,
+        }
+    }
 
     fn name(&self) -> &str {
         "Mbc3"
     }
+
+    fn tick(&mut self, cycles: u32) {
+        if let Some(rtc) = &mut self.rtc {
+            rtc.tick(cycles);
+        }
+    }
+
+    fn rtc(&mut self) -> Option<&mut Rtc> {
+        self.rtc.as_mut()
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.ram_enabled);
+        state.u8(self.rom_bank);
+        state.u8(self.ram_select);
+        state.u8(self.latch);
+        if let Some(rtc) = &self.rtc {
+            rtc.save_state(state);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Error> {
+        self.ram_enabled = state.bool()?;
+        self.rom_bank = (state.u8()? & 0x7F).max(1);
+        self.ram_select = state.u8()?;
+        self.latch = state.u8()?;
+        if let Some(rtc) = &mut self.rtc {
+            rtc.load_state(state)?;
+        }
+        Ok(())
+    }
 }
 ////////////////////////////////////////////////////////////////////////////////
-pub struct Mbc5;
+// MBC5, up to 8 MiB of ROM and 128 KiB of RAM
+//
+// 0000-1FFF  RAM enable, 0x0A enables
+// 2000-2FFF  ROM bank, lower 8 bits, bank 0 can be selected
+// 3000-3FFF  ROM bank, bit 8
+// 4000-5FFF  RAM bank 0-15, rumble carts drive the motor with bit 3
+pub struct Mbc5 {
+    ram_enabled: bool,
+    rom_bank: u16,
+    ram_bank: u8,
+    // motor state on rumble carts
+    rumble: Option<bool>,
+    rumble_changed: bool,
+}
 impl Mbc5 {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(has_rumble: bool) -> Self {
+        Self {
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            rumble: has_rumble.then_some(false),
+            rumble_changed: false,
+        }
+    }
+
+    fn ram_offset(&self, addr: u16) -> usize {
+        // with a motor only 8 RAM banks are left
+        let mask = if self.rumble.is_some() { 0x07 } else { 0x0F };
+        (self.ram_bank & mask) as usize * 0x2000 + (addr as usize - 0xA000)
     }
 }
 impl Mbc for Mbc5 {
     fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
-        // If rom is empty, return 0xFF
-        rom.get(addr as usize).copied().unwrap_or(0xFF)
+        let offset = match addr {
+            0x0000..=0x3FFF => addr as usize,
+            _ => self.rom_bank as usize * 0x4000 + (addr as usize - 0x4000),
+        };
+        // banks past the end of the ROM mirror the ones before
+        rom.get(offset % rom.len().max(1)).copied().unwrap_or(0xFF)
     }
 
-    fn write_rom(&mut self, _: &mut [u8], _: u16, _: u8) {}
+    fn write_rom(&mut self, _: &mut [u8], addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | value as u16,
+            0x3000..=0x3FFF => self.rom_bank = (self.rom_bank & 0xFF) | (value as u16 & 0x01) << 8,
+            0x4000..=0x5FFF => {
+                self.ram_bank = value & 0x0F;
+                if let Some(rumble) = &mut self.rumble {
+                    let on = value & 0x08 != 0;
+                    self.rumble_changed |= *rumble != on;
+                    *rumble = on;
+                }
+            }
+            _ => {}
+        }
+    }
 
     fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
-        ram.get(addr as usize).copied().unwrap_or(0xFF)
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+        ram.get(self.ram_offset(addr)).copied().unwrap_or(0xFF)
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) {
+        if !self.ram_enabled {
+            return;
+        }
+        let offset = self.ram_offset(addr);
+        if let Some(byte) = ram.get_mut(offset) {
+            *byte = value;
+        }
     }
 
-    fn write_ram(&mut self, _: &mut [u8], _: u16, _: u8) {}
+    fn rom_bank(&self, addr: u16) -> usize {
+        match addr {
+            0x0000..=0x3FFF => 0,
+            _ => self.rom_bank as usize,
+        }
+    }
 
     fn name(&self) -> &str {
         "Mbc5"
     }
+
+    fn take_rumble(&mut self) -> Option<bool> {
+        std::mem::take(&mut self.rumble_changed)
+            .then_some(self.rumble)
+            .flatten()
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.ram_enabled);
+        state.u16(self.rom_bank);
+        state.u8(self.ram_bank);
+        state.bool(self.rumble == Some(true));
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Error> {
+        self.ram_enabled = state.bool()?;
+        self.rom_bank = state.u16()? & 0x1FF;
+        self.ram_bank = state.u8()? & 0x0F;
+        let on = state.bool()?;
+        if let Some(rumble) = &mut self.rumble {
+            self.rumble_changed |= *rumble != on;
+            *rumble = on;
+        }
+        Ok(())
+    }
 }
 ////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::CLOCK_SPEED;
+
+    // every byte holds the number of its 16 KiB bank
+    fn banked_rom(banks: usize) -> Vec<u8> {
+        (0..banks * 0x4000).map(|i| (i / 0x4000) as u8).collect()
+    }
+
+    #[test]
+    fn mbc3_switches_rom_and_ram_banks() {
+        let mut rom = banked_rom(128);
+        let mut ram = vec![0; 0x8000];
+        let mut mbc = Mbc3::new(false);
+
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 1);
+        mbc.write_rom(&mut rom, 0x2000, 0x00);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 1);
+        mbc.write_rom(&mut rom, 0x2000, 0xFF);
+        assert_eq!(mbc.read_rom(&rom, 0x7FFF), 0x7F);
+        assert_eq!(mbc.rom_bank(0x4000), 0x7F);
+        assert_eq!(mbc.read_rom(&rom, 0x0000), 0);
+
+        // RAM stays closed until enabled
+        mbc.write_ram(&mut ram, 0xA000, 0x11);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0xFF);
+        mbc.write_rom(&mut rom, 0x0000, 0x0A);
+        for bank in 0..4 {
+            mbc.write_rom(&mut rom, 0x4000, bank);
+            mbc.write_ram(&mut ram, 0xA123, bank + 0x10);
+        }
+        assert_eq!(ram[0x6123], 0x13);
+        mbc.write_rom(&mut rom, 0x4000, 1);
+        assert_eq!(mbc.read_ram(&ram, 0xA123), 0x11);
+    }
+
+    #[test]
+    fn mbc3_clock_is_mapped_and_latched() {
+        let mut rom = banked_rom(4);
+        let mut ram = vec![0; 0x2000];
+        let mut mbc = Mbc3::new(true);
+        mbc.write_rom(&mut rom, 0x0000, 0x0A);
+
+        // set the minutes through the mapped register
+        mbc.write_rom(&mut rom, 0x4000, 0x09);
+        mbc.write_ram(&mut ram, 0xA000, 42);
+        mbc.tick(CLOCK_SPEED as u32 * 3);
+
+        // nothing shows until latched by writing 0 then 1
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0);
+        mbc.write_rom(&mut rom, 0x6000, 0x01);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0);
+        mbc.write_rom(&mut rom, 0x6000, 0x00);
+        mbc.write_rom(&mut rom, 0x6000, 0x01);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 42);
+        mbc.write_rom(&mut rom, 0x4000, 0x08);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 3);
+        assert_eq!(ram[0], 0);
+    }
+
+    #[test]
+    fn mbc3_without_clock_ignores_clock_registers() {
+        let mut rom = banked_rom(4);
+        let mut ram = vec![0; 0x2000];
+        let mut mbc = Mbc3::new(false);
+        mbc.write_rom(&mut rom, 0x0000, 0x0A);
+        mbc.write_rom(&mut rom, 0x4000, 0x08);
+        mbc.write_ram(&mut ram, 0xA000, 5);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0xFF);
+        assert!(mbc.rtc().is_none());
+    }
+
+    #[test]
+    fn mbc5_selects_nine_bit_rom_banks() {
+        // 512 banks, the bank number's low byte in every byte
+        let mut rom = banked_rom(512);
+        let mut mbc = Mbc5::new(false);
+
+        mbc.write_rom(&mut rom, 0x2000, 0x00);
+        assert_eq!(mbc.rom_bank(0x4000), 0);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0);
+        mbc.write_rom(&mut rom, 0x3000, 0x01);
+        assert_eq!(mbc.rom_bank(0x4000), 0x100);
+        mbc.write_rom(&mut rom, 0x2FFF, 0x23);
+        assert_eq!(mbc.rom_bank(0x4000), 0x123);
+        assert_eq!(mbc.read_rom(&rom, 0x7FFF), 0x23);
+        mbc.write_rom(&mut rom, 0x3000, 0xFE);
+        assert_eq!(mbc.rom_bank(0x4000), 0x23);
+    }
+
+    #[test]
+    fn mbc5_switches_sixteen_ram_banks() {
+        let mut rom = banked_rom(4);
+        let mut ram = vec![0; 0x20000];
+        let mut mbc = Mbc5::new(false);
+
+        mbc.write_ram(&mut ram, 0xA000, 0x11);
+        assert_eq!(ram[0], 0);
+        mbc.write_rom(&mut rom, 0x0000, 0x0A);
+        for bank in 0..16 {
+            mbc.write_rom(&mut rom, 0x4000, bank);
+            mbc.write_ram(&mut ram, 0xBFFF, bank);
+        }
+        assert_eq!(ram[0x1FFFF], 15);
+        mbc.write_rom(&mut rom, 0x4000, 0x0A);
+        assert_eq!(mbc.read_ram(&ram, 0xBFFF), 10);
+        mbc.write_rom(&mut rom, 0x0000, 0x00);
+        assert_eq!(mbc.read_ram(&ram, 0xBFFF), 0xFF);
+    }
+
+    #[test]
+    fn mbc5_rumble_bit_drives_the_motor() {
+        let mut rom = banked_rom(4);
+        let mut ram = vec![0; 0x10000];
+        let mut mbc = Mbc5::new(true);
+        mbc.write_rom(&mut rom, 0x0000, 0x0A);
+
+        assert_eq!(mbc.take_rumble(), None);
+        mbc.write_rom(&mut rom, 0x4000, 0x0B);
+        assert_eq!(mbc.take_rumble(), Some(true));
+        assert_eq!(mbc.take_rumble(), None);
+
+        // the motor bit doesn't select RAM
+        mbc.write_ram(&mut ram, 0xA000, 0x33);
+        assert_eq!(ram[0x6000], 0x33);
+        mbc.write_rom(&mut rom, 0x4000, 0x03);
+        mbc.write_rom(&mut rom, 0x4000, 0x00);
+        assert_eq!(mbc.take_rumble(), Some(false));
+
+        // without a motor bit 3 is a bank bit
+        let mut mbc = Mbc5::new(false);
+        mbc.write_rom(&mut rom, 0x4000, 0x08);
+        assert_eq!(mbc.take_rumble(), None);
+    }
+
+    #[test]
+    fn mbc2_selects_registers_by_address_bit_8() {
+        let mut rom = banked_rom(16);
+        let ram = vec![0; 512];
+        let mut mbc = Mbc2::new();
+
+        // bit 8 set, ROM bank
+        mbc.write_rom(&mut rom, 0x2100, 0x0A);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x0A);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0xFF);
+        mbc.write_rom(&mut rom, 0x3FFF, 0xF0);
+        assert_eq!(mbc.rom_bank(0x4000), 1);
+
+        // bit 8 clear, RAM enable, anywhere in 0000-3FFF
+        mbc.write_rom(&mut rom, 0x20FF, 0x0A);
+        assert_eq!(mbc.rom_bank(0x4000), 1);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0xF0);
+    }
+
+    #[test]
+    fn mbc2_ram_holds_mirrored_nibbles() {
+        let mut rom = banked_rom(2);
+        let mut ram = vec![0; 512];
+        let mut mbc = Mbc2::new();
+        mbc.write_rom(&mut rom, 0x0000, 0x0A);
+
+        mbc.write_ram(&mut ram, 0xA005, 0xAB);
+        assert_eq!(ram[5], 0x0B);
+        assert_eq!(mbc.read_ram(&ram, 0xA005), 0xFB);
+        assert_eq!(mbc.read_ram(&ram, 0xA205), 0xFB);
+        assert_eq!(mbc.read_ram(&ram, 0xBE05), 0xFB);
+        mbc.write_ram(&mut ram, 0xBFFF, 0x07);
+        assert_eq!(ram[0x1FF], 0x07);
+    }
+
+    #[test]
+    fn mbc1_mode_1_banks_the_zero_region() {
+        // 2 MiB
+        let mut rom = banked_rom(128);
+        let mut mbc = Mbc1::new(rom.len(), false);
+
+        mbc.write_rom(&mut rom, 0x4000, 0x02);
+        mbc.write_rom(&mut rom, 0x2000, 0x03);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x43);
+        assert_eq!(mbc.read_rom(&rom, 0x0000), 0x00);
+
+        mbc.write_rom(&mut rom, 0x6000, 0x01);
+        assert_eq!(mbc.read_rom(&rom, 0x0000), 0x40);
+        assert_eq!(mbc.rom_bank(0x3FFF), 0x40);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x43);
+
+        // writing 0 selects 1 only by the 5 low bits, 0x20 stays unreachable
+        mbc.write_rom(&mut rom, 0x4000, 0x01);
+        mbc.write_rom(&mut rom, 0x2000, 0x00);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x21);
+    }
+
+    #[test]
+    fn mbc1_masks_banks_by_rom_and_ram_size() {
+        // 512 KiB, 32 banks, the upper bits don't exist
+        let mut rom = banked_rom(32);
+        let mut mbc = Mbc1::new(rom.len(), false);
+        mbc.write_rom(&mut rom, 0x4000, 0x03);
+        mbc.write_rom(&mut rom, 0x2000, 0x05);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x05);
+        mbc.write_rom(&mut rom, 0x6000, 0x01);
+        assert_eq!(mbc.read_rom(&rom, 0x0000), 0x00);
+
+        // 64 KiB, bank 5 is bank 1
+        let mut rom = banked_rom(4);
+        let mut mbc = Mbc1::new(rom.len(), false);
+        mbc.write_rom(&mut rom, 0x2000, 0x05);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x01);
+
+        // 8 KiB of RAM, every RAM bank is the same
+        let mut ram = vec![0; 0x2000];
+        mbc.write_rom(&mut rom, 0x0000, 0x0A);
+        mbc.write_rom(&mut rom, 0x6000, 0x01);
+        mbc.write_rom(&mut rom, 0x4000, 0x02);
+        mbc.write_ram(&mut ram, 0xA010, 0x77);
+        assert_eq!(ram[0x10], 0x77);
+
+        // 32 KiB, 4 banks in mode 1 only
+        let mut ram = vec![0; 0x8000];
+        mbc.write_ram(&mut ram, 0xA010, 0x77);
+        assert_eq!(ram[0x4010], 0x77);
+        mbc.write_rom(&mut rom, 0x6000, 0x00);
+        assert_eq!(mbc.read_ram(&ram, 0xA010), 0x00);
+    }
+
+    #[test]
+    fn mbc1_multicart_wires_four_low_bits() {
+        // 1 MiB, four games of 16 banks
+        let mut rom = banked_rom(64);
+        let mut mbc = Mbc1::new(rom.len(), true);
+
+        mbc.write_rom(&mut rom, 0x2000, 0x12);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x02);
+        // only the full 5 bits are checked for 0
+        mbc.write_rom(&mut rom, 0x2000, 0x10);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x00);
+
+        mbc.write_rom(&mut rom, 0x4000, 0x02);
+        mbc.write_rom(&mut rom, 0x2000, 0x03);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x23);
+        mbc.write_rom(&mut rom, 0x6000, 0x01);
+        assert_eq!(mbc.read_rom(&rom, 0x0000), 0x20);
+    }
+}
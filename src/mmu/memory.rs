@@ -1,3 +1,7 @@
+use std::io::Error;
+
+use crate::state::{SaveState, StateReader, StateWriter};
+
 pub struct Memory {
     pub vram: [u8; 0x2000],
     pub wram: [u8; 0x2000],
@@ -15,3 +19,25 @@ impl Memory {
         }
     }
 }
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SaveState for Memory {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&self.vram);
+        state.bytes(&self.wram);
+        state.bytes(&self.hram);
+        state.bytes(&self.oam);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Error> {
+        state.bytes(&mut self.vram)?;
+        state.bytes(&mut self.wram)?;
+        state.bytes(&mut self.hram)?;
+        state.bytes(&mut self.oam)
+    }
+}
@@ -1,13 +1,24 @@
 pub mod cartridge;
+pub mod code_cache;
+pub mod dma;
 pub mod mbc;
 pub mod memory;
+pub mod rtc;
 use std::io::Error;
 
 use crate::{
-    apu::Apu, interrupt_controller::InterruptController, joypad::Joypad, ppu::Ppu,
-    serial::SerialPort, timer::Timer,
+    apu::Apu,
+    cpu::{bus::Bus, decoder::DecodedInstruction},
+    interrupt_controller::{Interrupt, InterruptController},
+    joypad::Joypad,
+    ppu::Ppu,
+    serial::SerialPort,
+    state::{SaveState, StateReader, StateWriter, invalid},
+    timer::Timer,
 };
 use cartridge::Cartridge;
+use code_cache::CodeCache;
+use dma::Dma;
 use memory::Memory;
 
 // Game Boy Memory Map
@@ -26,7 +37,8 @@ use memory::Memory;
 // FF04–FF07   Timer
 // FF0F        IF (Interrupt Flag)
 // FF10–FF3F   APU
-// FF40–FF4B   PPU Registers
+// FF40–FF4B   PPU Registers (FF46 OAM DMA)
+// FF4D        KEY1 (CGB speed switch)
 // FF80–FFFE   HRAM
 // FFFF        IE (Interrupt Enable)
 
@@ -42,6 +54,23 @@ pub struct Mmu {
     pub interrupts: InterruptController,
     pub serial: SerialPort,
     pub apu: Apu,
+    pub dma: Dma,
+    pub code_cache: CodeCache,
+    // ROM banks mapped at 0000-3FFF and 4000-7FFF, refreshed on MBC writes
+    rom_banks: [usize; 2],
+
+    // CGB mode is picked from the cartridge header
+    pub cgb: bool,
+    pub double_speed: bool,
+    speed_switch_armed: bool,
+
+    // when set, every CPU memory access advances the rest of the machine
+    // by one M-cycle before it happens
+    pub m_cycle_accurate: bool,
+    stepped_cycles: u32,
+
+    // Gameboy Doctor logs are recorded with LY reading $90
+    pub doctor: bool,
 }
 
 #[allow(clippy::too_many_arguments)] // TODO: reconsider
@@ -65,6 +94,15 @@ impl Mmu {
             interrupts,
             serial,
             apu,
+            dma: Dma::new(),
+            code_cache: CodeCache::new(),
+            rom_banks: [0, 1],
+            cgb: false,
+            double_speed: false,
+            speed_switch_armed: false,
+            m_cycle_accurate: false,
+            stepped_cycles: 0,
+            doctor: false,
         }
     }
 
@@ -81,6 +119,9 @@ impl Mmu {
             0xFF04..=0xFF07 => self.timer.read_reg(addr),
             0xFF0F => self.interrupts.iflag,
             0xFF10..=0xFF3F => self.apu.read_reg(addr),
+            0xFF46 => self.dma.source,
+            0xFF4D => self.read_key1(),
+            0xFF44 if self.doctor => 0x90,
             0xFF40..=0xFF4B => self.ppu.read_reg(addr),
             0xFF80..=0xFFFE => self.read_hram(addr),
             0xFFFF => self.interrupts.ie,
@@ -97,11 +138,13 @@ impl Mmu {
             0xE000..=0xFDFF => self.write_echo(addr, value),
             0xFE00..=0xFE9F => self.write_oam(addr, value),
             0xFEA0..=0xFEFF => {}
-            0xFF00 => self.joypad.write_reg(value),
+            0xFF00 => self.joypad.write_reg(value, &mut self.interrupts),
             0xFF01..=0xFF02 => self.serial.write_reg(addr, value),
             0xFF04..=0xFF07 => self.timer.write_reg(addr, value),
             0xFF0F => self.interrupts.iflag = (value & 0x1F) | 0xE0,
             0xFF10..=0xFF3F => self.apu.write_reg(addr, value),
+            0xFF46 => self.dma.start(value),
+            0xFF4D => self.write_key1(value),
             0xFF40..=0xFF4B => self.ppu.write_reg(addr, value),
             0xFF68..=0xFF69 => self.ppu.write_reg(addr, value),
             0xFF4F => self.ppu.write_reg(addr, value),
@@ -113,24 +156,83 @@ impl Mmu {
         }
     }
 
-    pub fn read_16(&self, addr: u16) -> u16 {
-        let lo = self.read_8(addr) as u16;
-        let hi = self.read_8(addr.wrapping_add(1)) as u16;
-        hi << 8 | lo
-    }
-
     pub fn tick(&mut self, cycles: u32) {
-        self.ppu.tick(cycles, &self.memory.vram, &self.memory.oam);
+        // in double speed mode the PPU keeps running at the normal rate
+        let ppu_cycles = if self.double_speed {
+            cycles / 2
+        } else {
+            cycles
+        };
+        self.ppu
+            .tick(ppu_cycles, &self.memory.vram, &self.memory.oam);
+        // so does the cartridge clock
+        if let Some(cart) = &mut self.cartridge {
+            cart.mbc.tick(ppu_cycles);
+        }
         self.timer.tick(cycles, &mut self.interrupts);
+        self.tick_dma(cycles);
+    }
+
+    // returns the cycles already advanced by CPU accesses since the last call
+    pub fn take_stepped_cycles(&mut self) -> u32 {
+        std::mem::take(&mut self.stepped_cycles)
+    }
+
+    fn step_m_cycle(&mut self) {
+        if self.m_cycle_accurate {
+            self.tick(4);
+            self.stepped_cycles += 4;
+        }
+    }
+
+    fn tick_dma(&mut self, cycles: u32) {
+        self.dma.tick(cycles);
+        while let Some((src, dst)) = self.dma.next_transfer() {
+            self.memory.oam[dst] = self.read_8(src);
+        }
     }
 
     pub fn load_rom(&mut self, path: &str) -> Result<(), Error> {
         let cartridge = Cartridge::new(path)?;
+        self.cgb = cartridge.cgb;
+        self.double_speed = false;
+        self.speed_switch_armed = false;
         self.cartridge = Some(cartridge);
+        self.code_cache.clear();
+        self.refresh_rom_banks();
         Ok(())
     }
 
+    // has to follow anything that changes the MBC state
+    pub fn refresh_rom_banks(&mut self) {
+        if let Some(cart) = &self.cartridge {
+            self.rom_banks = [cart.mbc.rom_bank(0x0000), cart.mbc.rom_bank(0x4000)];
+        }
+    }
+
+    pub fn speed_switch_armed(&self) -> bool {
+        self.cgb && self.speed_switch_armed
+    }
+
+    pub fn switch_speed(&mut self) {
+        self.double_speed = !self.double_speed;
+        self.speed_switch_armed = false;
+    }
+
     // Helper functions
+    fn read_key1(&self) -> u8 {
+        if !self.cgb {
+            return 0xFF;
+        }
+        ((self.double_speed as u8) << 7) | 0x7E | self.speed_switch_armed as u8
+    }
+
+    fn write_key1(&mut self, value: u8) {
+        if self.cgb {
+            self.speed_switch_armed = value & 0x01 != 0;
+        }
+    }
+
     fn read_rom(&self, addr: u16) -> u8 {
         let cart = self.cartridge.as_ref().expect("Cartridge not loaded");
         cart.mbc.read_rom(&cart.rom, addr)
@@ -139,6 +241,8 @@ impl Mmu {
     fn write_rom(&mut self, addr: u16, value: u8) {
         let cart = self.cartridge.as_mut().expect("Cartridge not loaded");
         cart.mbc.write_rom(&mut cart.rom, addr, value);
+        // possibly a bank switch
+        self.refresh_rom_banks();
     }
 
     fn read_vram(&self, addr: u16) -> u8 {
@@ -159,6 +263,8 @@ impl Mmu {
     fn write_cartridge_ram(&mut self, addr: u16, value: u8) {
         let cart = self.cartridge.as_mut().expect("Cartridge not loaded");
         cart.mbc.write_ram(&mut cart.ram, addr, value);
+        // with RAM disabled nothing was written, saving again is harmless
+        cart.ram_dirty = true;
     }
 
     fn read_wram(&self, addr: u16) -> u8 {
@@ -169,6 +275,7 @@ impl Mmu {
     fn write_wram(&mut self, addr: u16, value: u8) {
         let offset = addr - 0xC000;
         self.memory.wram[offset as usize] = value;
+        self.code_cache.invalidate(addr);
     }
 
     fn read_echo(&self, addr: u16) -> u8 {
@@ -197,5 +304,228 @@ impl Mmu {
     fn write_hram(&mut self, addr: u16, value: u8) {
         let offset = addr - 0xFF80;
         self.memory.hram[offset as usize] = value;
+        self.code_cache.invalidate(addr);
+    }
+}
+
+impl SaveState for Mmu {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.memory.save_state(state);
+        state.bool(self.cartridge.is_some());
+        if let Some(cart) = &self.cartridge {
+            cart.save_state(state);
+        }
+        self.timer.save_state(state);
+        self.ppu.save_state(state);
+        self.joypad.save_state(state);
+        self.interrupts.save_state(state);
+        self.serial.save_state(state);
+        self.apu.save_state(state);
+        self.dma.save_state(state);
+        state.bool(self.cgb);
+        state.bool(self.double_speed);
+        state.bool(self.speed_switch_armed);
+        state.u32(self.stepped_cycles);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Error> {
+        self.memory.load_state(state)?;
+        match (state.bool()?, &mut self.cartridge) {
+            (true, Some(cart)) => cart.load_state(state)?,
+            (false, None) => {}
+            _ => return Err(invalid("save state is for a different cartridge")),
+        }
+        self.timer.load_state(state)?;
+        self.ppu.load_state(state)?;
+        self.joypad.load_state(state)?;
+        self.interrupts.load_state(state)?;
+        self.serial.load_state(state)?;
+        self.apu.load_state(state)?;
+        self.dma.load_state(state)?;
+        self.cgb = state.bool()?;
+        self.double_speed = state.bool()?;
+        self.speed_switch_armed = state.bool()?;
+        self.stepped_cycles = state.u32()?;
+
+        // memory and banks changed under the cache's feet
+        self.code_cache.clear();
+        self.refresh_rom_banks();
+        Ok(())
+    }
+}
+
+impl Bus for Mmu {
+    fn cpu_read_8(&mut self, addr: u16) -> u8 {
+        self.step_m_cycle();
+        self.read_8(addr)
+    }
+
+    fn cpu_write_8(&mut self, addr: u16, value: u8) {
+        self.step_m_cycle();
+        self.write_8(addr, value);
+    }
+
+    fn cpu_idle(&mut self) {
+        self.step_m_cycle();
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.read_8(addr)
+    }
+
+    fn pending_interrupts(&self) -> u8 {
+        self.interrupts.pending_mask()
+    }
+
+    fn highest_interrupt(&self) -> Option<Interrupt> {
+        self.interrupts.highest()
+    }
+
+    fn ack_interrupt(&mut self, irq: Interrupt) {
+        self.interrupts.ack(irq);
+    }
+
+    fn joypad_lines(&self) -> u8 {
+        self.joypad.lines()
+    }
+
+    fn cached_instruction(&mut self, addr: u16) -> Option<DecodedInstruction> {
+        if !self.code_cache.enabled {
+            return None;
+        }
+        if self.cartridge.is_none() && addr < 0x8000 {
+            return None;
+        }
+        let bank = match addr {
+            0x0000..=0x3FFF => self.rom_banks[0],
+            0x4000..=0x7FFF => self.rom_banks[1],
+            _ => 0,
+        };
+        if let Some(cached) = self.code_cache.get(bank, addr) {
+            return Some(cached);
+        }
+        let bytes = [
+            self.read_8(addr),
+            self.read_8(addr.wrapping_add(1)),
+            self.read_8(addr.wrapping_add(2)),
+        ];
+        self.code_cache.insert(bank, addr, bytes)
+    }
+
+    fn rom_bank(&self, addr: u16) -> u16 {
+        match (&self.cartridge, addr) {
+            (Some(cartridge), 0x0000..=0x7FFF) => cartridge.mbc.rom_bank(addr) as u16,
+            _ => 0,
+        }
+    }
+
+    fn stop(&mut self) -> bool {
+        self.timer.reset_div();
+        if !self.speed_switch_armed() {
+            return false;
+        }
+        self.switch_speed();
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mmu() -> Mmu {
+        Mmu::new(
+            Memory::new(),
+            None,
+            Timer::new(),
+            Ppu::new(),
+            Joypad::new(),
+            InterruptController::new(),
+            SerialPort::new(),
+            Apu::new(),
+        )
+    }
+
+    #[test]
+    fn cpu_accesses_step_timer_when_m_cycle_accurate() {
+        let mut mmu = mmu();
+        mmu.write_8(0xFF07, 0x05); // timer on, 16 cycles per TIMA increment
+
+        for _ in 0..4 {
+            mmu.cpu_read_8(0xC000);
+        }
+        assert_eq!(mmu.read_8(0xFF05), 0);
+        assert_eq!(mmu.take_stepped_cycles(), 0);
+
+        mmu.m_cycle_accurate = true;
+        for _ in 0..3 {
+            mmu.cpu_read_8(0xC000);
+        }
+        mmu.cpu_write_8(0xC000, 0x42);
+        assert_eq!(mmu.read_8(0xFF05), 1);
+        assert_eq!(mmu.take_stepped_cycles(), 16);
+        assert_eq!(mmu.take_stepped_cycles(), 0);
+    }
+
+    #[test]
+    fn oam_dma_copies_one_byte_per_m_cycle() {
+        let mut mmu = mmu();
+        for i in 0..0xA0u16 {
+            mmu.write_8(0xC000 + i, i as u8 ^ 0x5A);
+        }
+
+        mmu.write_8(0xFF46, 0xC0);
+        assert_eq!(mmu.read_8(0xFF46), 0xC0);
+
+        mmu.tick(8);
+        assert_eq!(mmu.memory.oam[0], 0x5A);
+        assert_eq!(mmu.memory.oam[1], 0x01 ^ 0x5A);
+        assert_eq!(mmu.memory.oam[2], 0);
+
+        mmu.tick(158 * 4);
+        for i in 0..0xA0 {
+            assert_eq!(mmu.memory.oam[i], i as u8 ^ 0x5A);
+        }
+    }
+
+    #[test]
+    fn code_cache_drops_instructions_overwritten_in_ram() {
+        let mut mmu = mmu();
+        // LD BC,1234
+        mmu.write_8(0xC000, 0x01);
+        mmu.write_8(0xC001, 0x34);
+        mmu.write_8(0xC002, 0x12);
+        let cached = mmu.cached_instruction(0xC000).unwrap();
+        assert_eq!(cached.operands, [0x34, 0x12]);
+
+        // through the echo mirror, into the last operand byte
+        mmu.write_8(0xE002, 0x56);
+        let cached = mmu.cached_instruction(0xC000).unwrap();
+        assert_eq!(cached.operands, [0x34, 0x56]);
+    }
+
+    #[test]
+    fn code_cache_keeps_rom_banks_apart() {
+        let mut rom = vec![0u8; 0x10000];
+        rom[0x147] = 0x01; // MBC1
+        rom[0x4000] = 0x3C; // bank 1: INC A
+        rom[0x8000] = 0x04; // bank 2: INC B
+        let mut mmu = mmu();
+        mmu.cartridge = Some(Cartridge {
+            rom,
+            ram: Vec::new(),
+            mbc: Box::new(mbc::Mbc1::new(0x10000, false)),
+            cgb: false,
+            save_path: None,
+            ram_dirty: false,
+        });
+
+        let inc =
+            |mmu: &mut Mmu| format!("{:?}", mmu.cached_instruction(0x4000).unwrap().entry.opcode);
+        assert_eq!(inc(&mut mmu), "IncR8(A)");
+        mmu.write_8(0x2000, 0x02);
+        assert_eq!(inc(&mut mmu), "IncR8(B)");
+        mmu.write_8(0x2000, 0x01);
+        assert_eq!(inc(&mut mmu), "IncR8(A)");
     }
 }
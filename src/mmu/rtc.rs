@@ -0,0 +1,372 @@
+use std::io::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::emulator::CLOCK_SPEED;
+use crate::state::{StateReader, StateWriter};
+
+// size of the clock appended to .sav files, see `Rtc::trailer`
+pub const TRAILER_SIZE: usize = 48;
+
+// DH register
+const DAY_HIGH: u8 = 0x01;
+const HALT: u8 = 0x40;
+const DAY_CARRY: u8 = 0x80;
+
+// Clock registers as selected through 0x08-0x0C
+//
+// 08  seconds   0-59
+// 09  minutes   0-59
+// 0A  hours     0-23
+// 0B  day counter, lower 8 bits
+// 0C  bit 0 day counter bit 8, bit 6 halt, bit 7 day counter carry
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Registers {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    day_low: u8,
+    day_high: u8,
+}
+
+impl Registers {
+    fn read(&self, select: u8) -> u8 {
+        match select {
+            0x08 => self.seconds,
+            0x09 => self.minutes,
+            0x0A => self.hours,
+            0x0B => self.day_low,
+            0x0C => self.day_high,
+            _ => 0xFF,
+        }
+    }
+
+    fn to_array(self) -> [u8; 5] {
+        [
+            self.seconds,
+            self.minutes,
+            self.hours,
+            self.day_low,
+            self.day_high,
+        ]
+    }
+
+    fn from_array(values: [u8; 5]) -> Self {
+        let [seconds, minutes, hours, day_low, day_high] = values;
+        Self {
+            seconds: seconds & 0x3F,
+            minutes: minutes & 0x3F,
+            hours: hours & 0x1F,
+            day_low,
+            day_high: day_high & (DAY_HIGH | HALT | DAY_CARRY),
+        }
+    }
+
+    // Counters wrap at their bit width, so a value written out of range
+    // counts up to the limit of its bits before wrapping to 0, without
+    // carrying into the next one
+    fn advance_second(&mut self) {
+        self.seconds = (self.seconds + 1) & 0x3F;
+        if self.seconds != 60 {
+            return;
+        }
+        self.seconds = 0;
+        self.minutes = (self.minutes + 1) & 0x3F;
+        if self.minutes != 60 {
+            return;
+        }
+        self.minutes = 0;
+        self.hours = (self.hours + 1) & 0x1F;
+        if self.hours != 24 {
+            return;
+        }
+        self.hours = 0;
+        let (day_low, overflow) = self.day_low.overflowing_add(1);
+        self.day_low = day_low;
+        if overflow {
+            if self.day_high & DAY_HIGH != 0 {
+                self.day_high = (self.day_high & !DAY_HIGH) | DAY_CARRY;
+            } else {
+                self.day_high |= DAY_HIGH;
+            }
+        }
+    }
+
+    fn in_range(&self) -> bool {
+        self.seconds < 60 && self.minutes < 60 && self.hours < 24
+    }
+
+    // Same as that many `advance_second` calls, the host clock can be years
+    // ahead of a .sav
+    fn advance(&mut self, mut seconds: u64) {
+        // out of range counters take at most a few hours to wrap back
+        while seconds > 0 && !self.in_range() {
+            self.advance_second();
+            seconds -= 1;
+        }
+        if seconds == 0 {
+            return;
+        }
+
+        let days = self.day_low as u64 | ((self.day_high & DAY_HIGH) as u64) << 8;
+        let total = ((days * 24 + self.hours as u64) * 60 + self.minutes as u64) * 60
+            + self.seconds as u64
+            + seconds;
+        self.seconds = (total % 60) as u8;
+        self.minutes = (total / 60 % 60) as u8;
+        self.hours = (total / 3600 % 24) as u8;
+        let days = total / 86400;
+        self.day_low = days as u8;
+        // the carry stays set until the game clears it
+        let mut day_high = self.day_high & (HALT | DAY_CARRY);
+        if days >= 512 {
+            day_high |= DAY_CARRY;
+        }
+        if days & 0x100 != 0 {
+            day_high |= DAY_HIGH;
+        }
+        self.day_high = day_high;
+    }
+}
+
+// MBC3 real time clock. It counts emulated time by default, which keeps
+// runs reproducible, or follows the host clock like the cartridge would
+// when `host_time` is set.
+pub struct Rtc {
+    current: Registers,
+    latched: Registers,
+    // T-cycles into the current second
+    cycles: u32,
+    host_time: bool,
+    // unix time the registers were last brought up to date
+    synced_at: u64,
+}
+
+impl Rtc {
+    pub fn new() -> Self {
+        Self {
+            current: Registers::default(),
+            latched: Registers::default(),
+            cycles: 0,
+            host_time: false,
+            synced_at: unix_time(),
+        }
+    }
+
+    // Switching to host time catches up from the last sync, right after
+    // loading a .sav that covers the time the game was off
+    pub fn set_host_time(&mut self, on: bool) {
+        // leaving host time, bring the registers up to date first
+        if !on {
+            self.sync();
+        }
+        self.host_time = on;
+        self.sync();
+    }
+
+    fn halted(&self) -> bool {
+        self.current.day_high & HALT != 0
+    }
+
+    // `cycles` at the normal clock rate, the RTC has its own crystal
+    pub fn tick(&mut self, cycles: u32) {
+        if self.host_time || self.halted() {
+            return;
+        }
+        self.cycles += cycles;
+        while self.cycles >= CLOCK_SPEED as u32 {
+            self.cycles -= CLOCK_SPEED as u32;
+            self.current.advance_second();
+        }
+    }
+
+    // catches up with the host clock, a clock saved without a time or in
+    // the future has nothing to catch up on
+    fn sync(&mut self) {
+        let now = unix_time();
+        if self.host_time && !self.halted() && self.synced_at != 0 {
+            self.current.advance(now.saturating_sub(self.synced_at));
+        }
+        self.synced_at = now;
+    }
+
+    pub fn latch(&mut self) {
+        self.sync();
+        self.latched = self.current;
+    }
+
+    pub fn read(&self, select: u8) -> u8 {
+        self.latched.read(select)
+    }
+
+    pub fn write(&mut self, select: u8, value: u8) {
+        self.sync();
+        let mut values = self.current.to_array();
+        if let Some(register) = values.get_mut(select.wrapping_sub(0x08) as usize) {
+            *register = value;
+        }
+        if select == 0x08 {
+            // writing the seconds restarts the current one
+            self.cycles = 0;
+        }
+        self.current = Registers::from_array(values);
+    }
+
+    // The format most emulators append to .sav files, every field little
+    // endian: the five current registers and the five latched ones as u32
+    // each, then the unix time of the save as u64
+    pub fn trailer(&mut self) -> [u8; TRAILER_SIZE] {
+        self.sync();
+        let mut trailer = [0; TRAILER_SIZE];
+        let registers = self.current.to_array().into_iter();
+        let latched = self.latched.to_array().into_iter();
+        for (i, value) in registers.chain(latched).enumerate() {
+            trailer[i * 4..i * 4 + 4].copy_from_slice(&(value as u32).to_le_bytes());
+        }
+        trailer[40..].copy_from_slice(&self.synced_at.to_le_bytes());
+        trailer
+    }
+
+    pub fn load_trailer(&mut self, trailer: &[u8; TRAILER_SIZE]) {
+        let value = |i: usize| trailer[i * 4];
+        self.current = Registers::from_array(std::array::from_fn(value));
+        self.latched = Registers::from_array(std::array::from_fn(|i| value(i + 5)));
+        self.synced_at = u64::from_le_bytes(trailer[40..].try_into().unwrap());
+        self.cycles = 0;
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&self.current.to_array());
+        state.bytes(&self.latched.to_array());
+        state.u32(self.cycles);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), Error> {
+        let mut values = [0; 5];
+        state.bytes(&mut values)?;
+        self.current = Registers::from_array(values);
+        state.bytes(&mut values)?;
+        self.latched = Registers::from_array(values);
+        self.cycles = state.u32()?;
+        // a state from the past doesn't make up for the time since
+        self.synced_at = unix_time();
+        Ok(())
+    }
+}
+
+impl Default for Rtc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rtc_at(values: [u8; 5]) -> Rtc {
+        let mut rtc = Rtc::new();
+        for (select, value) in (0x08..).zip(values) {
+            rtc.write(select, value);
+        }
+        rtc
+    }
+
+    fn latched(rtc: &mut Rtc) -> [u8; 5] {
+        rtc.latch();
+        std::array::from_fn(|i| rtc.read(0x08 + i as u8))
+    }
+
+    #[test]
+    fn counts_emulated_seconds_into_days() {
+        // day 511, 23:59:58
+        let mut rtc = rtc_at([58, 59, 23, 0xFF, DAY_HIGH]);
+        rtc.tick(CLOCK_SPEED as u32 - 1);
+        assert_eq!(latched(&mut rtc), [58, 59, 23, 0xFF, DAY_HIGH]);
+        rtc.tick(1);
+        assert_eq!(latched(&mut rtc), [59, 59, 23, 0xFF, DAY_HIGH]);
+        rtc.tick(CLOCK_SPEED as u32);
+        assert_eq!(latched(&mut rtc), [0, 0, 0, 0, DAY_CARRY]);
+    }
+
+    #[test]
+    fn halt_stops_the_clock_and_reads_stay_latched() {
+        let mut rtc = rtc_at([10, 0, 0, 0, HALT]);
+        rtc.tick(CLOCK_SPEED as u32 * 5);
+        assert_eq!(latched(&mut rtc)[0], 10);
+
+        rtc.write(0x0C, 0);
+        rtc.tick(CLOCK_SPEED as u32 * 5);
+        // still the value of the last latch
+        assert_eq!(rtc.read(0x08), 10);
+        assert_eq!(latched(&mut rtc)[0], 15);
+    }
+
+    #[test]
+    fn out_of_range_values_wrap_without_carry() {
+        let mut rtc = rtc_at([63, 5, 0, 0, 0]);
+        rtc.tick(CLOCK_SPEED as u32);
+        assert_eq!(latched(&mut rtc)[..2], [0, 5]);
+    }
+
+    #[test]
+    fn trailer_round_trips_and_catches_up_in_host_mode() {
+        let mut rtc = rtc_at([1, 2, 3, 4, DAY_HIGH]);
+        rtc.latch();
+        let mut trailer = rtc.trailer();
+        assert_eq!(trailer[..8], [1, 0, 0, 0, 2, 0, 0, 0]);
+
+        let mut loaded = Rtc::new();
+        loaded.load_trailer(&trailer);
+        assert_eq!(latched(&mut loaded), [1, 2, 3, 4, DAY_HIGH]);
+
+        // saved 90 seconds ago
+        let saved_at = unix_time() - 90;
+        trailer[40..].copy_from_slice(&saved_at.to_le_bytes());
+        let mut loaded = Rtc::new();
+        loaded.load_trailer(&trailer);
+        loaded.set_host_time(true);
+        let time = latched(&mut loaded);
+        assert!(time[1] == 3 && (31..=33).contains(&time[0]), "{:?}", time);
+    }
+
+    #[test]
+    fn trailer_without_a_time_does_not_catch_up() {
+        let mut rtc = rtc_at([1, 2, 3, 4, DAY_HIGH]);
+        let mut trailer = rtc.trailer();
+        trailer[40..].fill(0);
+
+        let mut loaded = Rtc::new();
+        loaded.load_trailer(&trailer);
+        loaded.set_host_time(true);
+        assert_eq!(latched(&mut loaded), [1, 2, 3, 4, DAY_HIGH]);
+    }
+
+    #[test]
+    fn long_catch_ups_match_counting_seconds() {
+        // day 511, 23:59:58, past the end of the day counter and 3 days on
+        let mut rtc = rtc_at([58, 59, 23, 0xFF, DAY_HIGH]);
+        rtc.current.advance(2 + 3 * 86400 + 3661);
+        assert_eq!(latched(&mut rtc), [1, 1, 1, 3, DAY_CARRY]);
+
+        // to day 256
+        let mut rtc = rtc_at([0, 0, 0, 0xFF, 0]);
+        rtc.current.advance(86400);
+        assert_eq!(latched(&mut rtc), [0, 0, 0, 0, DAY_HIGH]);
+
+        for start in [[63, 5, 0, 0, 0], [10, 59, 31, 7, 0], [59, 59, 23, 0xFF, 0]] {
+            let mut counted = Registers::from_array(start);
+            for _ in 0..200_000 {
+                counted.advance_second();
+            }
+            let mut rtc = rtc_at(start);
+            rtc.current.advance(200_000);
+            assert_eq!(rtc.current, counted, "{:?}", start);
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use std::fs;
+use std::io::Error;
+
+use crate::interrupt_controller::InterruptController;
+use crate::joypad::Joypad;
+use crate::state::{StateReader, StateWriter, invalid};
+
+const MAGIC: &[u8; 4] = b"GBMV";
+const FORMAT_VERSION: u16 = 1;
+
+// Movie file, all numbers little endian
+//
+// "GBMV"
+// u16       format version
+// u8 + ...  emulator version string
+// u32       CRC-32 of the ROM
+// u32       event count
+// events:   u64 frame, u8 joypad state (see `Joypad::state`)
+//
+// Frames are counted from power on. Input only changes on frame boundaries,
+// both while recording and during playback, which makes playback exact.
+pub struct Movie {
+    pub emulator_version: String,
+    pub rom_checksum: u32,
+    pub events: Vec<(u64, u8)>,
+    pub mode: MovieMode,
+    pub frame: u64,
+    next_event: usize,
+    // input gathered during the current frame while recording
+    pending: u8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MovieMode {
+    Recording,
+    Playing,
+    Finished,
+}
+
+impl Movie {
+    pub fn record(rom_checksum: u32) -> Self {
+        Self {
+            emulator_version: env!("CARGO_PKG_VERSION").to_string(),
+            rom_checksum,
+            events: Vec::new(),
+            mode: MovieMode::Recording,
+            frame: 0,
+            next_event: 0,
+            pending: 0,
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Self, Error> {
+        Self::decode(&fs::read(path)?)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Error> {
+        fs::write(path, self.encode())
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = StateWriter::new();
+        data.bytes(MAGIC);
+        data.u16(FORMAT_VERSION);
+        data.u8(self.emulator_version.len() as u8);
+        data.bytes(self.emulator_version.as_bytes());
+        data.u32(self.rom_checksum);
+        data.u32(self.events.len() as u32);
+        for &(frame, state) in &self.events {
+            data.u64(frame);
+            data.u8(state);
+        }
+        data.data
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, Error> {
+        let mut reader = StateReader::new(data);
+        let mut magic = [0; 4];
+        reader.bytes(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a movie file"));
+        }
+        if reader.u16()? != FORMAT_VERSION {
+            return Err(invalid("unsupported movie format version"));
+        }
+        let mut version = vec![0; reader.u8()? as usize];
+        reader.bytes(&mut version)?;
+        let emulator_version = String::from_utf8_lossy(&version).to_string();
+        let rom_checksum = reader.u32()?;
+
+        let count = reader.u32()?;
+        let mut events = Vec::new();
+        for _ in 0..count {
+            let frame = reader.u64()?;
+            let state = reader.u8()?;
+            events.push((frame, state));
+        }
+
+        Ok(Self {
+            emulator_version,
+            rom_checksum,
+            events,
+            mode: MovieMode::Playing,
+            frame: 0,
+            next_event: 0,
+            pending: 0,
+        })
+    }
+
+    // live input, only takes effect on the next frame boundary
+    pub fn set_input(&mut self, state: u8) {
+        self.pending = state;
+    }
+
+    pub fn input(&self) -> u8 {
+        self.pending
+    }
+
+    // called whenever the PPU completes a frame
+    pub fn end_frame(&mut self, joypad: &mut Joypad, interrupts: &mut InterruptController) {
+        match self.mode {
+            MovieMode::Recording => {
+                if self.pending != joypad.state() {
+                    joypad.set_state(self.pending, interrupts);
+                    self.events.push((self.frame, self.pending));
+                }
+            }
+            MovieMode::Playing => {
+                while let Some(&(frame, state)) = self.events.get(self.next_event)
+                    && frame <= self.frame
+                {
+                    joypad.set_state(state, interrupts);
+                    self.next_event += 1;
+                }
+                if self.next_event == self.events.len() {
+                    self.mode = MovieMode::Finished;
+                }
+            }
+            MovieMode::Finished => {}
+        }
+        self.frame += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::joypad::Button;
+
+    #[test]
+    fn encoding_round_trips() {
+        let mut movie = Movie::record(0xDEADBEEF);
+        movie.events = vec![(3, 0x80), (10, 0x00), (u64::MAX, 0xFF)];
+
+        let decoded = Movie::decode(&movie.encode()).unwrap();
+        assert_eq!(decoded.emulator_version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(decoded.rom_checksum, 0xDEADBEEF);
+        assert_eq!(decoded.events, movie.events);
+        assert_eq!(decoded.mode, MovieMode::Playing);
+
+        let encoded = movie.encode();
+        assert!(Movie::decode(&encoded[..encoded.len() - 1]).is_err());
+        assert!(Movie::decode(b"GBSS").is_err());
+    }
+
+    #[test]
+    fn input_changes_on_frame_boundaries() {
+        let mut interrupts = InterruptController::new();
+        let mut joypad = Joypad::new();
+        let mut movie = Movie::record(0);
+
+        movie.end_frame(&mut joypad, &mut interrupts);
+        movie.set_input(1 << 7); // Start
+        assert!(!joypad.is_pressed(Button::Start));
+        movie.end_frame(&mut joypad, &mut interrupts);
+        assert!(joypad.is_pressed(Button::Start));
+        movie.end_frame(&mut joypad, &mut interrupts);
+        movie.set_input(0);
+        movie.end_frame(&mut joypad, &mut interrupts);
+        assert_eq!(movie.events, vec![(1, 0x80), (3, 0x00)]);
+
+        // playback applies the same states on the same frames
+        let mut movie = Movie::decode(&movie.encode()).unwrap();
+        let mut joypad = Joypad::new();
+        let mut pressed = Vec::new();
+        for _ in 0..4 {
+            movie.end_frame(&mut joypad, &mut interrupts);
+            pressed.push(joypad.is_pressed(Button::Start));
+        }
+        assert_eq!(pressed, vec![false, true, true, false]);
+        assert_eq!(movie.mode, MovieMode::Finished);
+    }
+}
@@ -1,6 +1,9 @@
 pub mod registers;
 pub mod renderer;
 
+use std::io::Error;
+
+use crate::state::{SaveState, StateReader, StateWriter, invalid};
 use registers::PpuRegisters;
 use renderer::Framebuffer;
 
@@ -45,9 +48,7 @@ impl Ppu {
         }
     }
 
-    fn tick_dot(&mut self, vram: &[u8], oam: &[u8]) {
-        self.frame_complete = false;
-
+    fn tick_dot(&mut self, vram: &[u8], _oam: &[u8]) {
         // resetting
         if self.registers.lcdc & 0x80 == 0 {
             self.mode = PpuMode::HBlank;
@@ -155,10 +156,15 @@ impl Ppu {
         (self.registers.bgp >> (color * 2)) & 0x03
     }
 
+    // a frame completed since the last `clear_frame_ready`
     pub fn frame_ready(&self) -> bool {
         self.frame_complete
     }
 
+    pub fn clear_frame_ready(&mut self) {
+        self.frame_complete = false;
+    }
+
     pub fn read_reg(&self, addr: u16) -> u8 {
         match addr {
             0xFF40 => self.registers.lcdc,
@@ -196,3 +202,40 @@ impl Ppu {
         }
     }
 }
+
+impl Default for Ppu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SaveState for Ppu {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u16(self.line_dots);
+        state.u8(self.px_x);
+        state.u8(self.ly);
+        self.registers.save_state(state);
+        state.bytes(&self.framebuffer.pixels);
+        state.bool(self.frame_complete);
+        state.u8(self.mode as u8);
+        state.bool(self.stat_irq_line);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Error> {
+        self.line_dots = state.u16()?;
+        self.px_x = state.u8()?;
+        self.ly = state.u8()?;
+        self.registers.load_state(state)?;
+        state.bytes(&mut self.framebuffer.pixels)?;
+        self.frame_complete = state.bool()?;
+        self.mode = match state.u8()? {
+            0 => PpuMode::HBlank,
+            1 => PpuMode::VBlank,
+            2 => PpuMode::OamSearch,
+            3 => PpuMode::PixelTransfer,
+            _ => return Err(invalid("invalid PPU mode")),
+        };
+        self.stat_irq_line = state.bool()?;
+        Ok(())
+    }
+}
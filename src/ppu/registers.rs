@@ -1,3 +1,7 @@
+use std::io::Error;
+
+use crate::state::{SaveState, StateReader, StateWriter};
+
 pub struct PpuRegisters {
     pub lcdc: u8, // FF40
     pub stat: u8, // FF41
@@ -27,3 +31,37 @@ impl PpuRegisters {
         }
     }
 }
+
+impl Default for PpuRegisters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SaveState for PpuRegisters {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&[
+            self.lcdc, self.stat, self.scy, self.scx, self.lyc, self.bgp, self.obp0, self.obp1,
+            self.wy, self.wx,
+        ]);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Error> {
+        let mut values = [0; 10];
+        state.bytes(&mut values)?;
+        let [lcdc, stat, scy, scx, lyc, bgp, obp0, obp1, wy, wx] = values;
+        *self = Self {
+            lcdc,
+            stat,
+            scy,
+            scx,
+            lyc,
+            bgp,
+            obp0,
+            obp1,
+            wy,
+            wx,
+        };
+        Ok(())
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::VecDeque;
+
+// one capture per frame, rewinding steps back a single frame
+pub const DEFAULT_INTERVAL: u32 = 1;
+pub const DEFAULT_BUDGET: usize = 32 * 1024 * 1024;
+
+// Ring of save states captured every `interval` frames. Only the newest
+// state is kept whole, every older one is stored as the difference to the
+// state after it, so the oldest entries can be dropped when the buffer
+// goes over its memory budget without breaking the chain.
+//
+// Every capture carries a position, the emulator's tick count, which only
+// ever grows from one capture to the next.
+pub struct Rewind {
+    pub interval: u32,
+    pub budget: usize,
+    current: Option<Vec<u8>>,
+    position: u64,
+    // oldest first, `deltas[i]` turns state i + 1 back into state i
+    deltas: VecDeque<Delta>,
+    delta_bytes: usize,
+    frames_since_capture: u32,
+}
+
+struct Delta {
+    // of the state this delta restores
+    position: u64,
+    data: Vec<u8>,
+}
+
+impl Rewind {
+    pub fn new(interval: u32, budget: usize) -> Self {
+        Self {
+            interval: interval.max(1),
+            budget,
+            current: None,
+            position: 0,
+            deltas: VecDeque::new(),
+            delta_bytes: 0,
+            frames_since_capture: 0,
+        }
+    }
+
+    // returns whether a capture is due
+    pub fn end_frame(&mut self) -> bool {
+        self.frames_since_capture += 1;
+        self.current.is_none() || self.frames_since_capture >= self.interval
+    }
+
+    pub fn push(&mut self, state: Vec<u8>, position: u64) {
+        if let Some(older) = &self.current {
+            let data = compress(older, &state);
+            self.delta_bytes += data.len();
+            self.deltas.push_back(Delta {
+                position: self.position,
+                data,
+            });
+        }
+        self.current = Some(state);
+        self.position = position;
+        self.frames_since_capture = 0;
+
+        while self.memory() > self.budget
+            && let Some(oldest) = self.deltas.pop_front()
+        {
+            self.delta_bytes -= oldest.data.len();
+        }
+    }
+
+    // The state to go back to. Part way into an interval that is the last
+    // capture, right after one it is the capture before.
+    pub fn step_back(&mut self) -> Option<Vec<u8>> {
+        if self.frames_since_capture == 0 {
+            self.pop()?;
+        }
+        self.frames_since_capture = 0;
+        self.current.clone()
+    }
+
+    // Drops every capture after `position` and returns the newest one left
+    // with its position. Nothing changes when all captures are later.
+    pub fn seek(&mut self, position: u64) -> Option<(u64, Vec<u8>)> {
+        let oldest = self.deltas.front().map_or(self.position, |d| d.position);
+        if self.current.is_none() || oldest > position {
+            return None;
+        }
+        while self.position > position {
+            self.pop()?;
+        }
+        self.frames_since_capture = 0;
+        Some((self.position, self.current.clone()?))
+    }
+
+    // Every capture with its position, newest first, leaving the buffer as
+    // it is
+    pub fn captures(&self) -> impl Iterator<Item = (u64, Vec<u8>)> + '_ {
+        let mut state = self.current.clone();
+        let mut position = self.position;
+        let mut deltas = self.deltas.iter().rev();
+        std::iter::from_fn(move || {
+            let current = state.take()?;
+            let at = position;
+            if let Some(delta) = deltas.next() {
+                state = Some(decompress(&delta.data, &current));
+                position = delta.position;
+            }
+            Some((at, current))
+        })
+    }
+
+    // turns the newest capture back into the one before it
+    fn pop(&mut self) -> Option<()> {
+        let delta = self.deltas.pop_back()?;
+        self.delta_bytes -= delta.data.len();
+        let current = self.current.as_mut()?;
+        *current = decompress(&delta.data, current);
+        self.position = delta.position;
+        Some(())
+    }
+
+    pub fn clear(&mut self) {
+        self.current = None;
+        self.deltas.clear();
+        self.delta_bytes = 0;
+        self.frames_since_capture = 0;
+    }
+
+    // states that can still be reached
+    pub fn len(&self) -> usize {
+        self.current.as_ref().map_or(0, |_| self.deltas.len() + 1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.current.is_none()
+    }
+
+    pub fn memory(&self) -> usize {
+        self.delta_bytes + self.current.as_ref().map_or(0, Vec::len)
+    }
+}
+
+// Delta encoding: the length of `older`, then the XOR of both states as
+// alternating (zero run, literal run) pairs. Consecutive frames differ in
+// a few hundred bytes, so the zero runs make up nearly all of it.
+fn compress(older: &[u8], newer: &[u8]) -> Vec<u8> {
+    let length = older.len().max(newer.len());
+    let byte = |data: &[u8], i: usize| data.get(i).copied().unwrap_or(0);
+    let xor = |i: usize| byte(older, i) ^ byte(newer, i);
+
+    let mut delta = Vec::new();
+    write_varint(&mut delta, older.len());
+    let mut i = 0;
+    while i < length {
+        let zeros = (i..length).take_while(|&j| xor(j) == 0).count();
+        i += zeros;
+        let literals = (i..length).take_while(|&j| xor(j) != 0).count();
+        write_varint(&mut delta, zeros);
+        write_varint(&mut delta, literals);
+        delta.extend((i..i + literals).map(xor));
+        i += literals;
+    }
+    delta
+}
+
+fn decompress(delta: &[u8], newer: &[u8]) -> Vec<u8> {
+    let mut pos = 0;
+    let length = read_varint(delta, &mut pos);
+    let mut older = newer.to_vec();
+    older.resize(length.max(newer.len()), 0);
+
+    let mut i = 0;
+    while pos < delta.len() {
+        i += read_varint(delta, &mut pos);
+        let literals = read_varint(delta, &mut pos);
+        for byte in &mut older[i..i + literals] {
+            *byte ^= delta[pos];
+            pos += 1;
+        }
+        i += literals;
+    }
+    older.truncate(length);
+    older
+}
+
+// LEB128, 7 bits per byte with the high bit set on all but the last
+fn write_varint(data: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        data.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    data.push(value as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*pos];
+        *pos += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(frame: u8, length: usize) -> Vec<u8> {
+        let mut state = vec![0xAA; length];
+        state[10] = frame;
+        state[length - 1] = frame.wrapping_mul(3);
+        state
+    }
+
+    #[test]
+    fn deltas_restore_older_states() {
+        let older = state(1, 300);
+        let newer = state(2, 320);
+        let delta = compress(&older, &newer);
+        assert!(delta.len() < 40);
+        assert_eq!(decompress(&delta, &newer), older);
+        assert_eq!(decompress(&compress(&newer, &older), &older), newer);
+    }
+
+    #[test]
+    fn steps_back_one_capture_at_a_time() {
+        let mut rewind = Rewind::new(2, DEFAULT_BUDGET);
+        for frame in 0..6 {
+            if rewind.end_frame() {
+                rewind.push(state(frame, 64), frame as u64);
+            }
+        }
+        // captured on frames 0, 2 and 4, one frame into the next interval
+        assert_eq!(rewind.len(), 3);
+        assert_eq!(rewind.step_back(), Some(state(4, 64)));
+        assert_eq!(rewind.step_back(), Some(state(2, 64)));
+        assert_eq!(rewind.step_back(), Some(state(0, 64)));
+        assert_eq!(rewind.step_back(), None);
+    }
+
+    #[test]
+    fn oldest_states_are_dropped_over_budget() {
+        let mut rewind = Rewind::new(1, 1024);
+        for frame in 0..100 {
+            rewind.end_frame();
+            rewind.push(state(frame, 512), frame as u64);
+        }
+        assert!(rewind.memory() <= 1024);
+        assert!(rewind.len() < 100);
+
+        let reachable = rewind.len();
+        let mut last = None;
+        while let Some(state) = rewind.step_back() {
+            last = Some(state);
+        }
+        assert_eq!(last, Some(state(100 - reachable as u8, 512)));
+    }
+
+    #[test]
+    fn seeking_drops_later_captures() {
+        let mut rewind = Rewind::new(1, DEFAULT_BUDGET);
+        for frame in 1..=5 {
+            rewind.end_frame();
+            rewind.push(state(frame, 64), frame as u64 * 100);
+        }
+        assert_eq!(rewind.seek(50), None);
+        assert_eq!(rewind.len(), 5);
+
+        assert_eq!(rewind.seek(350), Some((300, state(3, 64))));
+        assert_eq!(rewind.len(), 3);
+        assert_eq!(rewind.seek(300), Some((300, state(3, 64))));
+        assert_eq!(rewind.step_back(), Some(state(2, 64)));
+    }
+
+    #[test]
+    fn captures_are_walked_without_dropping_them() {
+        let mut rewind = Rewind::new(1, DEFAULT_BUDGET);
+        for frame in 1..=3 {
+            rewind.end_frame();
+            rewind.push(state(frame, 64), frame as u64 * 100);
+        }
+        let captures: Vec<_> = rewind.captures().collect();
+        assert_eq!(
+            captures,
+            vec![
+                (300, state(3, 64)),
+                (200, state(2, 64)),
+                (100, state(1, 64))
+            ]
+        );
+        assert_eq!(rewind.len(), 3);
+    }
+}
@@ -1,3 +1,7 @@
+use std::io::Error;
+
+use crate::state::{SaveState, StateReader, StateWriter};
+
 pub struct SerialPort {
     pub sb: u8,
     pub sc: u8,
@@ -35,3 +39,24 @@ impl SerialPort {
         }
     }
 }
+
+impl Default for SerialPort {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SaveState for SerialPort {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.sb);
+        state.u8(self.sc);
+        state.block(self.output.as_bytes());
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Error> {
+        self.sb = state.u8()?;
+        self.sc = state.u8()?;
+        self.output = String::from_utf8_lossy(state.block()?).to_string();
+        Ok(())
+    }
+}
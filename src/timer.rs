@@ -1,4 +1,7 @@
+use std::io::Error;
+
 use crate::interrupt_controller::{Interrupt, InterruptController};
+use crate::state::{SaveState, StateReader, StateWriter};
 
 fn tima_period(tac: u8) -> Option<u32> {
     if tac & 0x04 == 0 {
@@ -83,3 +86,28 @@ impl Timer {
         self.div = 0;
     }
 }
+
+impl Default for Timer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SaveState for Timer {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u16(self.div);
+        state.u8(self.tima);
+        state.u8(self.tma);
+        state.u8(self.tac);
+        state.u32(self.tima_counter);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Error> {
+        self.div = state.u16()?;
+        self.tima = state.u8()?;
+        self.tma = state.u8()?;
+        self.tac = state.u8()?;
+        self.tima_counter = state.u32()?;
+        Ok(())
+    }
+}
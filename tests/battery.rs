@@ -0,0 +1,33 @@
+mod common;
+
+use gameboy_rs::emulator::{CLOCK_SPEED, Emulator};
+
+#[test]
+fn battery_ram_is_flushed_with_the_lcd_off() {
+    let rom_path = common::battery_rom(
+        "flush.gb",
+        &[
+            0xAF, // XOR A
+            0xE0, 0x40, // LDH ($40),A, LCD off
+            0x3E, 0x0A, // LD A,$0A
+            0xEA, 0x00, 0x00, // LD ($0000),A, enable RAM
+            0x3E, 0x42, // LD A,$42
+            0xEA, 0x00, 0xA0, // LD ($A000),A
+            0x18, 0xFE, // JR -2
+        ],
+    );
+    let sav_path = common::temp("flush.sav");
+
+    let mut emulator = Emulator::new();
+    emulator.load_rom(&rom_path).unwrap();
+    let mut cycles = 0;
+    while cycles < CLOCK_SPEED * 2 {
+        cycles += emulator.tick() as u64;
+    }
+    assert!(!emulator.mmu.ppu.frame_ready());
+
+    let sav = std::fs::read(&sav_path);
+    std::fs::remove_file(&rom_path).unwrap();
+    let _ = std::fs::remove_file(&sav_path);
+    assert_eq!(sav.unwrap()[0], 0x42);
+}
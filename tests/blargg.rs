@@ -0,0 +1,31 @@
+mod common;
+
+use common::rom;
+use gameboy_rs::debug::runner::{DEFAULT_CYCLE_BUDGET, TestResult, run_test_rom};
+
+fn run(name: &str) {
+    let report = run_test_rom(&rom(name), DEFAULT_CYCLE_BUDGET).unwrap();
+    println!("{}", report.output);
+    assert_eq!(
+        report.result,
+        TestResult::Passed,
+        "{}: {}",
+        name,
+        report.summary()
+    );
+}
+
+#[test]
+fn cpu_instrs() {
+    run("test.gb");
+}
+
+#[test]
+fn interrupts() {
+    run("test_int.gb");
+}
+
+#[test]
+fn misc_instrs() {
+    run("test_misc.gb");
+}
@@ -0,0 +1,47 @@
+// Fixtures shared by the integration tests, each test crate only uses
+// part of them
+#![allow(dead_code)]
+
+use gameboy_rs::emulator::Emulator;
+
+pub fn rom(name: &str) -> String {
+    format!("{}/roms/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+// unique per test process, the caller removes it
+pub fn temp(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("{}-{}", std::process::id(), name));
+    path.to_string_lossy().to_string()
+}
+
+// MBC1+RAM+BATTERY with 8 KiB of RAM running `code` from $0100, written
+// to a temp file. Its save is the same path with a .sav extension.
+pub fn battery_rom(name: &str, code: &[u8]) -> String {
+    let mut rom = vec![0u8; 0x8000];
+    rom[0x147] = 0x03;
+    rom[0x149] = 0x02;
+    rom[0x100..0x100 + code.len()].copy_from_slice(code);
+    let path = temp(name);
+    std::fs::write(&path, &rom).unwrap();
+    path
+}
+
+pub fn boot(name: &str) -> Emulator {
+    let mut emulator = Emulator::new();
+    emulator.load_rom(&rom(name)).unwrap();
+    emulator
+}
+
+// runs to the end of each frame, `input` is called with the frame number
+// before it starts
+pub fn run_frames(emulator: &mut Emulator, frames: u32, mut input: impl FnMut(&mut Emulator, u32)) {
+    for frame in 0..frames {
+        input(emulator, frame);
+        emulator.tick();
+        while !emulator.mmu.ppu.frame_ready() {
+            emulator.tick();
+        }
+    }
+}
+
+pub fn no_input(_: &mut Emulator, _: u32) {}
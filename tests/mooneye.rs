@@ -0,0 +1,31 @@
+// Cartridge tests from the Mooneye test suite,
+// https://github.com/Gekkio/mooneye-test-suite
+//
+// The ROMs are not part of the repository. Point MOONEYE_DIR at a build of
+// the suite, the directory holding `emulator-only/`, and run the ignored
+// tests with `cargo test --test mooneye -- --ignored`.
+
+use std::env;
+
+use gameboy_rs::debug::runner::{MOONEYE_CYCLE_BUDGET, TestResult, format_table, run_mooneye_dir};
+
+fn run_suite(subdir: &str) {
+    let root = env::var("MOONEYE_DIR").expect("MOONEYE_DIR is not set");
+    let dir = format!("{}/{}", root, subdir);
+    let reports = run_mooneye_dir(&dir, MOONEYE_CYCLE_BUDGET)
+        .unwrap_or_else(|e| panic!("can't read {}: {}", dir, e));
+    assert!(!reports.is_empty(), "no ROMs in {}", dir);
+    assert!(
+        reports
+            .iter()
+            .all(|(_, report)| report.result == TestResult::Passed),
+        "\n{}",
+        format_table(&reports)
+    );
+}
+
+#[test]
+#[ignore = "needs the Mooneye test suite, see MOONEYE_DIR"]
+fn mooneye_mbc1() {
+    run_suite("emulator-only/mbc1");
+}
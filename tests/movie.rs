@@ -0,0 +1,97 @@
+mod common;
+
+use common::{battery_rom, boot, run_frames, temp};
+use gameboy_rs::cpu::bus::Bus;
+use gameboy_rs::emulator::CLOCK_SPEED;
+use gameboy_rs::emulator::Emulator;
+use gameboy_rs::joypad::Button;
+
+const FRAMES: u32 = 600;
+
+// mash Start and A through the title screens into a game
+fn mash(emulator: &mut Emulator, frame: u32) {
+    let button = if frame % 60 < 30 {
+        Button::Start
+    } else {
+        Button::A
+    };
+    match frame % 10 {
+        0 => emulator.press(button),
+        5 => emulator.release(button),
+        _ => {}
+    }
+}
+
+#[test]
+fn playback_reproduces_recording() {
+    let path = temp("movie.gbm");
+
+    let mut recorder = boot("tetris.gb");
+    recorder.start_recording(&path).unwrap();
+    run_frames(&mut recorder, FRAMES, mash);
+    assert_eq!(recorder.stop_movie().unwrap(), Some(path.clone()));
+
+    let mut player = boot("tetris.gb");
+    player.play_movie(&path).unwrap();
+    // live input is ignored during playback
+    run_frames(&mut player, FRAMES, |emulator, _| emulator.press(Button::B));
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(player.cpu.registers, recorder.cpu.registers);
+    for addr in 0xC000..=0xDFFF {
+        assert_eq!(
+            player.mmu.peek(addr),
+            recorder.mmu.peek(addr),
+            "{:04X}",
+            addr
+        );
+    }
+}
+
+#[test]
+fn movies_leave_the_battery_save_alone() {
+    let rom_path = battery_rom(
+        "movie-battery.gb",
+        &[
+            0x3E, 0x0A, // LD A,$0A
+            0xEA, 0x00, 0x00, // LD ($0000),A, enable RAM
+            0x3E, 0x42, // LD A,$42
+            0xEA, 0x00, 0xA0, // LD ($A000),A
+            0x18, 0xFE, // JR -2
+        ],
+    );
+    let sav_path = temp("movie-battery.sav");
+    let movie_path = temp("movie-battery.gbm");
+    let save = vec![0x77; 8 * 1024];
+    std::fs::write(&sav_path, &save).unwrap();
+
+    let mut emulator = Emulator::new();
+    emulator.load_rom(&rom_path).unwrap();
+    assert_eq!(emulator.mmu.cartridge.as_ref().unwrap().ram[0], 0x77);
+
+    emulator.start_recording(&movie_path).unwrap();
+    // blank RAM, whatever the save on disk holds
+    assert_eq!(emulator.mmu.cartridge.as_ref().unwrap().ram[0], 0);
+    // longer than the periodic battery flush
+    let mut cycles = 0;
+    while cycles < CLOCK_SPEED * 2 {
+        cycles += emulator.tick() as u64;
+    }
+    let recorded = emulator.mmu.cartridge.as_ref().unwrap().ram[0];
+    emulator.stop_movie().unwrap();
+
+    emulator.play_movie(&movie_path).unwrap();
+    assert_eq!(emulator.mmu.cartridge.as_ref().unwrap().ram[0], 0);
+    let mut cycles = 0;
+    while cycles < CLOCK_SPEED * 2 {
+        cycles += emulator.tick() as u64;
+    }
+    emulator.stop_movie().unwrap();
+
+    let on_disk = std::fs::read(&sav_path).unwrap();
+    for path in [&rom_path, &sav_path, &movie_path] {
+        std::fs::remove_file(path).unwrap();
+    }
+    assert_eq!(recorded, 0x42);
+    assert!(on_disk == save);
+}
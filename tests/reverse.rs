@@ -0,0 +1,114 @@
+mod common;
+
+use common::{no_input, run_frames};
+use gameboy_rs::debug::reverse::{reverse_continue, step_back};
+use gameboy_rs::emulator::Emulator;
+use gameboy_rs::mmu::cartridge::Cartridge;
+use gameboy_rs::mmu::mbc::NoMbc;
+use gameboy_rs::rewind::DEFAULT_BUDGET;
+
+// INC A; INC B; JR -4
+fn emulator() -> Emulator {
+    with_rom(&[0x3C, 0x04, 0x18, 0xFC])
+}
+
+fn with_rom(code: &[u8]) -> Emulator {
+    let mut rom = vec![0u8; 0x8000];
+    rom[0x100..0x100 + code.len()].copy_from_slice(code);
+    let mut emulator = Emulator::new();
+    emulator.mmu.cartridge = Some(Cartridge {
+        rom,
+        ram: Vec::new(),
+        mbc: Box::new(NoMbc::new()),
+        cgb: false,
+        save_path: None,
+        ram_dirty: false,
+    });
+    // LCD on, or frames never end
+    emulator.mmu.ppu.write_reg(0xFF40, 0x80);
+    emulator.start_rewind(1, DEFAULT_BUDGET);
+    emulator
+}
+
+#[test]
+fn step_back_undoes_the_last_instruction() {
+    let mut emulator = emulator();
+    run_frames(&mut emulator, 3, no_input);
+    for _ in 0..100 {
+        emulator.tick();
+    }
+
+    let mut states = Vec::new();
+    for _ in 0..5 {
+        states.push(emulator.snapshot());
+        emulator.tick();
+    }
+    let instructions = emulator.cpu.instruction_number;
+
+    step_back(&mut emulator).unwrap();
+    assert_eq!(emulator.cpu.instruction_number, instructions - 1);
+    assert!(emulator.snapshot() == states[4]);
+    step_back(&mut emulator).unwrap();
+    assert!(emulator.snapshot() == states[3]);
+}
+
+#[test]
+fn step_back_crosses_captures() {
+    let mut emulator = emulator();
+    run_frames(&mut emulator, 3, no_input);
+    // right after a capture, the instruction before lies in the
+    // previous interval
+    let instructions = emulator.cpu.instruction_number;
+    step_back(&mut emulator).unwrap();
+    assert_eq!(emulator.cpu.instruction_number, instructions - 1);
+}
+
+#[test]
+fn reverse_continue_stops_at_the_previous_hit() {
+    let mut emulator = emulator();
+    run_frames(&mut emulator, 2, no_input);
+    emulator.add_breakpoint(0x0102);
+    run_frames(&mut emulator, 2, no_input);
+
+    assert!(reverse_continue(&mut emulator).unwrap());
+    assert_eq!(emulator.cpu.registers.pc, 0x0102);
+    let hit = emulator.ticks;
+
+    // strictly before the current position
+    assert!(reverse_continue(&mut emulator).unwrap());
+    assert_eq!(emulator.cpu.registers.pc, 0x0102);
+    assert!(emulator.ticks < hit);
+    assert_eq!(emulator.debug.breakpoints, vec![0x0102]);
+
+    // nothing before the first frame, ends on the oldest capture
+    emulator.debug.breakpoints.clear();
+    assert!(!reverse_continue(&mut emulator).unwrap());
+}
+
+#[test]
+fn step_back_keeps_the_backtrace() {
+    // CALL $0105; INC A; INC B; JR -4
+    let mut emulator = with_rom(&[0xCD, 0x05, 0x01, 0x00, 0x00, 0x3C, 0x04, 0x18, 0xFC]);
+    run_frames(&mut emulator, 3, no_input);
+    let backtrace = emulator.backtrace();
+    assert_eq!(backtrace.len(), 2);
+
+    // the instruction before lies in the previous capture interval, which
+    // starts long after the call
+    emulator.tick();
+    step_back(&mut emulator).unwrap();
+    assert_eq!(emulator.backtrace(), backtrace);
+}
+
+#[test]
+fn failed_step_back_leaves_everything_as_it_was() {
+    // DI; HALT, and with no interrupt enabled nothing wakes it up
+    let mut emulator = with_rom(&[0xF3, 0x76]);
+    run_frames(&mut emulator, 5, no_input);
+    let state = emulator.snapshot();
+    let captures = emulator.rewind.as_ref().unwrap().len();
+
+    assert!(step_back(&mut emulator).is_err());
+    assert!(emulator.snapshot() == state);
+    assert_eq!(emulator.rewind.as_ref().unwrap().len(), captures);
+}
@@ -0,0 +1,34 @@
+mod common;
+
+use common::{boot, no_input, run_frames};
+use gameboy_rs::emulator::Emulator;
+use gameboy_rs::rewind::DEFAULT_BUDGET;
+
+// the state at the end of each frame
+fn frame_states(emulator: &mut Emulator, frames: u32) -> Vec<Vec<u8>> {
+    let mut states = Vec::new();
+    for _ in 0..frames {
+        run_frames(emulator, 1, no_input);
+        states.push(emulator.snapshot());
+    }
+    states
+}
+
+#[test]
+fn rewinding_restores_earlier_frames() {
+    let mut emulator = boot("tetris.gb");
+    emulator.start_rewind(1, DEFAULT_BUDGET);
+
+    let states = frame_states(&mut emulator, 60);
+    assert_eq!(emulator.rewind(1).unwrap(), 1);
+    assert!(emulator.snapshot() == states[58]);
+    assert_eq!(emulator.rewind(10).unwrap(), 10);
+    assert!(emulator.snapshot() == states[48]);
+
+    // running on from there captures new frames over the old ones
+    let replayed = frame_states(&mut emulator, 5);
+    assert!(replayed[4] == states[53]);
+    assert_eq!(emulator.rewind(1000).unwrap(), 53);
+    assert!(emulator.snapshot() == states[0]);
+    assert_eq!(emulator.rewind(1).unwrap(), 0);
+}
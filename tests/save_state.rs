@@ -0,0 +1,88 @@
+mod common;
+
+use common::{boot, rom, run_frames, temp};
+use gameboy_rs::emulator::Emulator;
+use gameboy_rs::joypad::Button;
+
+// Start pressed now and then, so the game doesn't just sit on its title
+fn press_start(emulator: &mut Emulator, frame: u32) {
+    match frame % 20 {
+        0 => emulator.press(Button::Start),
+        10 => emulator.release(Button::Start),
+        _ => {}
+    }
+}
+
+// saving, running on and loading again has to replay the exact same frames
+fn assert_round_trip(name: &str) {
+    let path = temp(&format!("{}.ss0", name));
+    let mut emulator = boot(name);
+    run_frames(&mut emulator, 120, press_start);
+    emulator.save_state(&path).unwrap();
+
+    run_frames(&mut emulator, 60, press_start);
+    let expected = emulator.snapshot();
+    let registers = emulator.cpu.registers.clone();
+
+    // into a fresh machine as well, nothing may leak from the old one
+    let mut emulator = boot(name);
+    emulator.load_state(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    run_frames(&mut emulator, 60, press_start);
+
+    assert_eq!(emulator.cpu.registers, registers);
+    assert!(emulator.snapshot() == expected, "{} diverged", name);
+}
+
+#[test]
+fn rom_only_cartridge_round_trips() {
+    assert_round_trip("tetris.gb");
+}
+
+#[test]
+fn mbc1_cartridge_round_trips() {
+    assert_round_trip("test.gb");
+}
+
+#[test]
+fn states_for_other_roms_or_damaged_files_are_rejected() {
+    let path = temp("reject.ss0");
+    let mut tetris = boot("tetris.gb");
+    run_frames(&mut tetris, 10, press_start);
+    tetris.save_state(&path).unwrap();
+
+    let mut other = boot("test.gb");
+    let before = other.snapshot();
+    assert!(other.load_state(&path).is_err());
+
+    let mut data = std::fs::read(&path).unwrap();
+    data[0x100] ^= 0xFF;
+    std::fs::write(&path, &data).unwrap();
+    assert!(tetris.load_state(&path).is_err());
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(other.snapshot() == before);
+}
+
+#[test]
+fn slots_live_next_to_the_rom() {
+    let emulator = boot("tetris.gb");
+    assert_eq!(emulator.state_path("2"), rom("tetris.ss2"));
+    assert_eq!(emulator.state_path("/tmp/a.state"), "/tmp/a.state");
+}
+
+#[test]
+fn loading_is_refused_while_a_movie_runs() {
+    let path = temp("movie.ss0");
+    let movie = temp("movie.gbm");
+    let mut emulator = boot("tetris.gb");
+    emulator.save_state(&path).unwrap();
+
+    emulator.start_recording(&movie).unwrap();
+    assert!(emulator.load_state(&path).is_err());
+    emulator.stop_movie().unwrap();
+    emulator.load_state(&path).unwrap();
+
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&movie).unwrap();
+}
@@ -0,0 +1,248 @@
+// Single-step tests for the CPU, using the per-opcode JSON vectors from
+// https://github.com/SingleStepTests/sm83 (initial state, final state and
+// the bus activity of every M-cycle).
+//
+// The vectors are not part of the repository. Point SM83_TESTS_DIR at the
+// directory holding `00.json` .. `ff.json` and `cb 00.json` .. `cb ff.json`
+// (defaults to `tests/sm83/v1`) and run the ignored test with
+// `cargo test --test sm83 -- --ignored`.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use gameboy_rs::{
+    cpu::{
+        Cpu,
+        bus::{Access, Bus, RecordingBus},
+        decoder::decode,
+    },
+    interrupt_controller::Interrupt,
+};
+use serde::Deserialize;
+use serde_json::Value;
+
+const DEFAULT_DIR: &str = "tests/sm83/v1";
+const MAX_REPORTED: usize = 20;
+
+#[derive(Deserialize)]
+struct State {
+    pc: u16,
+    sp: u16,
+    a: u8,
+    b: u8,
+    c: u8,
+    d: u8,
+    e: u8,
+    f: u8,
+    h: u8,
+    l: u8,
+    #[serde(default)]
+    ime: u8,
+    ram: Vec<(u16, u8)>,
+}
+
+#[derive(Deserialize)]
+struct Case {
+    name: String,
+    initial: State,
+    #[serde(rename = "final")]
+    expected: State,
+    cycles: Vec<Value>,
+}
+
+// flat 64 KiB of RAM
+struct FlatBus {
+    memory: Vec<u8>,
+}
+
+impl FlatBus {
+    fn new() -> Self {
+        Self {
+            memory: vec![0; 0x10000],
+        }
+    }
+}
+
+impl Bus for FlatBus {
+    fn cpu_read_8(&mut self, addr: u16) -> u8 {
+        self.memory[addr as usize]
+    }
+
+    fn cpu_write_8(&mut self, addr: u16, value: u8) {
+        self.memory[addr as usize] = value;
+    }
+
+    fn cpu_idle(&mut self) {}
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.memory[addr as usize]
+    }
+
+    fn pending_interrupts(&self) -> u8 {
+        self.memory[0xFFFF] & self.memory[0xFF0F] & 0x1F
+    }
+
+    fn highest_interrupt(&self) -> Option<Interrupt> {
+        // the CPU is only stepped through single instructions here
+        None
+    }
+
+    fn ack_interrupt(&mut self, _irq: Interrupt) {}
+
+    fn joypad_lines(&self) -> u8 {
+        0x0F
+    }
+
+    fn stop(&mut self) -> bool {
+        false
+    }
+}
+
+// cycles are `[addr, value, "r-m"]` style entries, or null for internal cycles
+fn expected_accesses(cycles: &[Value]) -> Vec<Access> {
+    cycles
+        .iter()
+        .filter_map(|cycle| {
+            let entry = cycle.as_array()?;
+            let addr = entry.first()?.as_u64()? as u16;
+            let value = entry.get(1)?.as_u64()? as u8;
+            let pins = entry.get(2)?.as_str()?;
+            if pins.starts_with('r') {
+                Some(Access::Read(addr, value))
+            } else if pins.contains('w') {
+                Some(Access::Write(addr, value))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+fn run_case(case: &Case) -> Result<(), String> {
+    let mut cpu = Cpu::new();
+    let mut bus = RecordingBus::new(FlatBus::new());
+
+    let init = &case.initial;
+    cpu.registers.pc = init.pc;
+    cpu.registers.sp = init.sp;
+    cpu.registers.a = init.a;
+    cpu.registers.b = init.b;
+    cpu.registers.c = init.c;
+    cpu.registers.d = init.d;
+    cpu.registers.e = init.e;
+    cpu.registers.f = init.f;
+    cpu.registers.h = init.h;
+    cpu.registers.l = init.l;
+    cpu.interrupts.ime = init.ime != 0;
+    for &(addr, value) in &init.ram {
+        bus.inner.memory[addr as usize] = value;
+    }
+
+    let opcode = bus.cpu_read_8(cpu.registers.pc);
+    let cycles = cpu.execute_instruction(decode(opcode), &mut bus);
+
+    let exp = &case.expected;
+    let regs = &cpu.registers;
+    let got = [
+        ("pc", regs.pc, exp.pc),
+        ("sp", regs.sp, exp.sp),
+        ("a", regs.a as u16, exp.a as u16),
+        ("b", regs.b as u16, exp.b as u16),
+        ("c", regs.c as u16, exp.c as u16),
+        ("d", regs.d as u16, exp.d as u16),
+        ("e", regs.e as u16, exp.e as u16),
+        ("f", regs.f as u16, exp.f as u16),
+        ("h", regs.h as u16, exp.h as u16),
+        ("l", regs.l as u16, exp.l as u16),
+    ];
+    for (name, actual, expected) in got {
+        if actual != expected {
+            return Err(format!("{name}: got {actual:04X}, expected {expected:04X}"));
+        }
+    }
+
+    // EI only takes effect after the next instruction, count it as enabled
+    let ime = cpu.interrupts.ime || cpu.interrupts.ime_scheduled;
+    if ime != (exp.ime != 0) {
+        return Err(format!("ime: got {}, expected {}", ime as u8, exp.ime));
+    }
+
+    for &(addr, value) in &exp.ram {
+        let actual = bus.inner.memory[addr as usize];
+        if actual != value {
+            return Err(format!(
+                "ram[{addr:04X}]: got {actual:02X}, expected {value:02X}"
+            ));
+        }
+    }
+
+    let expected_cycles = case.cycles.len() as u32 * 4;
+    if cycles != expected_cycles {
+        return Err(format!("cycles: got {cycles}, expected {expected_cycles}"));
+    }
+
+    let expected = expected_accesses(&case.cycles);
+    let accesses: Vec<Access> = bus
+        .accesses
+        .into_iter()
+        .filter(|a| *a != Access::Idle)
+        .collect();
+    if accesses != expected {
+        return Err(format!("bus: got {:?}, expected {:?}", accesses, expected));
+    }
+
+    Ok(())
+}
+
+// runs every vector file in `dir`, panics listing the failures
+fn run_dir(dir: &Path) {
+    let entries = fs::read_dir(dir)
+        .unwrap_or_else(|e| panic!("no SM83 test vectors in {}: {}", dir.display(), e));
+
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    files.sort();
+    assert!(
+        !files.is_empty(),
+        "no SM83 test vectors in {}",
+        dir.display()
+    );
+
+    let mut total = 0;
+    let mut failures = Vec::new();
+
+    for file in &files {
+        let data = fs::read_to_string(file).unwrap();
+        let cases: Vec<Case> =
+            serde_json::from_str(&data).unwrap_or_else(|e| panic!("{}: {}", file.display(), e));
+
+        for case in &cases {
+            total += 1;
+            if let Err(reason) = run_case(case) {
+                failures.push(format!("{}: {}", case.name, reason));
+            }
+        }
+    }
+
+    for failure in failures.iter().take(MAX_REPORTED) {
+        eprintln!("{}", failure);
+    }
+    assert!(
+        failures.is_empty(),
+        "{} of {} cases failed across {} files",
+        failures.len(),
+        total,
+        files.len()
+    );
+}
+
+#[test]
+#[ignore = "needs the SingleStepTests vectors, see SM83_TESTS_DIR"]
+fn sm83_single_step_tests() {
+    let dir = PathBuf::from(env::var("SM83_TESTS_DIR").unwrap_or_else(|_| DEFAULT_DIR.into()));
+    run_dir(&dir);
+}
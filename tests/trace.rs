@@ -0,0 +1,27 @@
+mod common;
+
+use common::{boot, no_input, run_frames, temp};
+
+// first line of Gameboy Doctor's reference logs for the cpu_instrs ROMs
+const FIRST_LINE: &str =
+    "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02";
+
+#[test]
+fn trace_starts_like_the_gameboy_doctor_logs() {
+    let mut emulator = boot("test_int.gb");
+    run_frames(&mut emulator, 10, no_input);
+
+    let path = temp("doctor.log");
+    emulator.start_trace(&path).unwrap();
+    assert_eq!(emulator.mmu.read_8(0xFF44), 0x90);
+    for _ in 0..100 {
+        emulator.tick();
+    }
+    assert!(emulator.stop_trace().unwrap());
+    assert!(!emulator.mmu.doctor);
+
+    let log = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(log.lines().count(), 100);
+    assert_eq!(log.lines().next(), Some(FIRST_LINE));
+}